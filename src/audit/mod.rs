@@ -1,16 +1,23 @@
-use std::fs::{self, File};
+use std::{collections::BTreeMap, fs::{self, File}};
 
-use dialoguer::{Confirm, Select, MultiSelect};
+use dialoguer::{Confirm, Input, MultiSelect};
+use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
 use reqwest::Client;
 use comfy_table::modifiers::UTF8_ROUND_CORNERS;
 use comfy_table::presets::UTF8_FULL;
 use comfy_table::{modifiers::UTF8_SOLID_INNER_BORDERS, Table};
 use vauth::{Profile, VProfile, build_url};
+use indicatif::ProgressBar;
+use regex::Regex;
+use serde::de::DeserializeOwned;
 use anyhow::Result;
 
-use crate::{login::{login, get_org_id}, models::{audit::AuditItem, self, group::ItemIds}, helpers::select_selection};
+use crate::{login::{login, get_org_id, select_orgs}, models::{audit::{short_id, AuditItem, AuditItemRow, AuditItemRecord}, self, config::Vb356, group::ItemIds, page::Page}, helpers::select_item_type, cli::{ListArgs, AddArgs, RemoveArgs, FetchArgs, DirectoryFilter, ItemType, OutputFormat}, output::print_serialized, plan::resolve_any};
 
-pub fn confirm_action() {
+pub fn confirm_action(assume_yes: bool) {
+    if assume_yes {
+        return;
+    }
     if Confirm::new()
         .with_prompt("Do you want to continue?")
         .interact()
@@ -23,53 +30,67 @@ pub fn confirm_action() {
     };
 }
 
-pub async fn get_audit_items(username: &String, address: &String) -> Result<()> {
+pub async fn get_audit_items(vb365: &Vb356, args: &ListArgs) -> Result<()> {
+    let address = &vb365.address;
     let mut profile = Profile::get_profile(VProfile::VB365);
 
-    let client = login(username, address, &mut profile).await?;
-    let id = get_org_id(address, &profile, &client).await?;
+    let client = login(vb365, &mut profile).await?;
+    let orgs = select_orgs(address, &profile, &client, args.org.as_deref(), args.all_orgs).await?;
 
-    let response = audit_items(&id, address, &profile, &client).await?;
+    let mut rows: Vec<AuditItemRow> = Vec::new();
+    for org in orgs.iter() {
+        let response = audit_items(&org.id, address, &profile, &client).await?;
+        rows.extend(response.into_iter().map(|x| AuditItemRow {
+            org: org.name.clone(),
+            ..AuditItemRow::from(x)
+        }));
+    }
+
+    match args.format {
+        OutputFormat::Table => print_audit_table(&rows, args.all_orgs),
+        OutputFormat::Csv => {
+            let records: Vec<AuditItemRecord> = rows.iter().map(AuditItemRecord::from).collect();
+            print_serialized(&records, args.format)?
+        }
+        format => print_serialized(&rows, format)?,
+    }
+    Ok(())
+}
 
-    if response.is_empty() {
+fn print_audit_table(rows: &[AuditItemRow], show_org: bool) {
+    if rows.is_empty() {
         println!("No audit items found");
-        return Ok(());
+        return;
     }
 
     let mut table = Table::new();
+    let mut header = vec![
+        "Name",
+        "ID",
+        "Type",
+    ];
+    if show_org {
+        header.insert(0, "Organization");
+    }
 
     table
         .load_preset(UTF8_FULL)
         .apply_modifier(UTF8_ROUND_CORNERS)
         .apply_modifier(UTF8_SOLID_INNER_BORDERS)
-        .set_header(vec![
-            "Name",
-            "ID",
-            "Type",
-        ]);
-
-    for i in response {
-        let mut name = String::new();
-        let mut id = String::new();
-        if let Some(user) = i.user {
-            let short_id: Vec<&str> = user.id.split(":").collect();
-            name = user.display_name;
-            id = short_id[3].to_owned();
-        }
-        else if let Some(group) = i.group {
-            let short_id: Vec<&str> = group.id.split(":").collect();
-            name = group.display_name;
-            id = short_id[3].to_owned();
+        .set_header(header);
+
+    for row in rows {
+        let mut cells = vec![
+            row.display_name.as_str(),
+            row.short_id.as_str(),
+            row.type_field.as_str(),
+        ];
+        if show_org {
+            cells.insert(0, row.org.as_str());
         }
-
-        table.add_row(vec![
-            name,
-            id,
-            i.type_field,
-        ]);
+        table.add_row(cells);
     }
     print!("{table}");
-    Ok(())
 }
 
 pub async fn audit_items(
@@ -84,146 +105,602 @@ pub async fn audit_items(
     Ok(response)
 }
 
-pub async fn add_audit_items(username: &String, address: &String) -> Result<()> {
-    let mut profile = Profile::get_profile(VProfile::VB365);
+/// Number of items requested per page from the directory endpoints.
+const PAGE_SIZE: usize = 500;
 
-    let client = login(username, address, &mut profile).await?;
-    let org_id = get_org_id(address, &profile, &client).await?;
+/// Follows the offset/limit paging of a directory endpoint, keeping the same setId so the
+/// pages come from one consistent snapshot. Stops early once `max` items have been fetched.
+async fn fetch_all_pages<T: DeserializeOwned>(
+    end_point: &str,
+    params: &[(&str, String)],
+    label: &str,
+    address: &String,
+    profile: &Profile,
+    client: &Client,
+    max: Option<usize>,
+) -> Result<(Vec<T>, String), anyhow::Error> {
+    let progress = ProgressBar::new_spinner();
+    let mut results: Vec<T> = Vec::new();
+    let mut set_id: Option<String> = None;
+
+    while let Some(limit) = next_limit(results.len(), max) {
+        let url = build_url(address, &end_point.to_string(), profile)?;
+        let mut request = client
+            .get(&url)
+            .query(params)
+            .query(&[("offset", results.len()), ("limit", limit)]);
+        if let Some(set_id) = &set_id {
+            request = request.query(&[("setId", set_id)]);
+        }
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            progress.finish_and_clear();
+            let response_text = response.text().await?;
+            return Err(anyhow::anyhow!("Failed to get {}: {}", label, response_text));
+        }
+        let page: Page<T> = response.json().await?;
 
-    let selections = &["Users", "Groups"];
+        let page_len = page.results.len();
+        set_id = set_id.or(page.set_id);
+        results.extend(page.results);
+        progress.set_message(format!("Fetched {} {}", results.len(), label));
+        progress.tick();
 
-    let selection = select_selection(selections, "Select Users or Groups".to_string());
+        if is_last_page(page_len, limit, page.limit) {
+            break;
+        }
+    }
+    progress.finish_and_clear();
+    eprintln!("Fetched {} {}", results.len(), label);
 
-    let mut audit_items: Vec<AuditItem> = Vec::new();
+    Ok((results, set_id.unwrap_or_default()))
+}
 
-    let supported_user_types = vec!["User", "Shared", "Public"];
+/// Limit for the next page once `fetched` items are in, `None` when `max` has been reached.
+fn next_limit(fetched: usize, max: Option<usize>) -> Option<usize> {
+    match max {
+        Some(max) if fetched >= max => None,
+        Some(max) => Some(PAGE_SIZE.min(max - fetched)),
+        None => Some(PAGE_SIZE),
+    }
+}
 
-    let supported_group_types = vec![
-        "Office365",
-        "Security",
-        "Distribution",
-        "DynamicDistribution",
-    ];
+/// A short page is the last one. The server may cap the page size, so the length is compared
+/// with the limit it echoes back when that is smaller than the one requested.
+fn is_last_page(page_len: usize, requested: usize, echoed_limit: i64) -> bool {
+    let limit = match usize::try_from(echoed_limit) {
+        Ok(echoed) if echoed > 0 => echoed.min(requested),
+        _ => requested,
+    };
+    page_len == 0 || page_len < limit
+}
 
-    if selection == 0 {
-        let file = serde_json::from_str::<models::user::User>(&fs::read_to_string("users.json")?)?;
+/// Server-side name search and client-side filtering of the directory.
+struct CompiledFilter<'a> {
+    filter: &'a DirectoryFilter,
+    name_regex: Option<Regex>,
+}
 
-        let users_to_add = file.results;
-        println!("This will add the following users to the audit items:");
-        for user in users_to_add.iter() {
-            if supported_user_types.contains(&user.type_field.as_str()) {
-                println!(" {}", user.display_name);
-            }
-        }
-        confirm_action();
-        for user in users_to_add.iter() {
-            if supported_user_types.contains(&user.type_field.as_str()) {
-                let audit_item = AuditItem::from(user.clone());
-                audit_items.push(audit_item);
-            }
-        }
-    } else if selection == 1 {
-        let file =
-            serde_json::from_str::<models::group::Group>(&fs::read_to_string("groups.json")?)?;
-        let groups_to_add = file.results;
-        println!("This will add the following groups to the audit items:");
-        for group in groups_to_add.iter() {
-            if supported_group_types.contains(&group.type_field.as_str()) {
-                println!(" {}", group.display_name);
+impl<'a> CompiledFilter<'a> {
+    fn new(filter: &'a DirectoryFilter) -> Result<Self, anyhow::Error> {
+        let name_regex = match &filter.name_regex {
+            Some(pattern) => Some(Regex::new(pattern)?),
+            None => None,
+        };
+        Ok(CompiledFilter { filter, name_regex })
+    }
+
+    fn name_matches(&self, display_name: &str, name: &str) -> bool {
+        if let Some(search) = &self.filter.name {
+            let search = search.to_lowercase();
+            if !display_name.to_lowercase().contains(&search) && !name.to_lowercase().contains(&search) {
+                return false;
             }
         }
-        confirm_action();
-        for group in groups_to_add.iter() {
-            if supported_group_types.contains(&group.type_field.as_str()) {
-                let audit_item = AuditItem::from(group.clone());
-                audit_items.push(audit_item);
+        if let Some(regex) = &self.name_regex {
+            if !regex.is_match(display_name) && !regex.is_match(name) {
+                return false;
             }
         }
+        true
     }
-    let url_str = format!("Organizations/{org_id}/AuditItems");
-    let url = build_url(address, &url_str, &profile)?;
 
-    let res = client.post(url).json(&audit_items).send().await?;
+    fn location_matches(&self, location_type: &str) -> bool {
+        self.filter.location_type.is_empty()
+            || self.filter.location_type.iter().any(|x| x.eq_ignore_ascii_case(location_type))
+    }
 
-    let type_string = if selection == 0 { "Users" } else { "Groups" };
+    fn user_matches(&self, user: &models::user::Result) -> bool {
+        self.name_matches(&user.display_name, &user.name)
+            && self.location_matches(user.location_type.as_str())
+            && (self.filter.user_type.is_empty()
+                || self.filter.user_type.iter().any(|x| x.eq_ignore_ascii_case(user.type_field.as_str())))
+    }
 
-    if res.status().is_success() {
-        println!("{} added successfully!", type_string);
-    } else {
-        println!("{} failed to add!",  type_string);
-        let response_text = res.text().await?;
-        println!("{}", response_text);
+    fn group_matches(&self, group: &models::group::Result) -> bool {
+        self.name_matches(&group.display_name, &group.name)
+            && self.location_matches(group.location_type.as_str())
+            && (self.filter.group_type.is_empty()
+                || self.filter.group_type.iter().any(|x| x.eq_ignore_ascii_case(group.type_field.as_str())))
+            && self.filter.managed_by.as_ref().is_none_or(|x| {
+                group.managed_by.to_lowercase().contains(&x.to_lowercase())
+            })
     }
 
-    Ok(())
+    fn site_matches(&self, site: &models::site::Result) -> bool {
+        self.name_matches(site.display_name(), &site.url) && self.location_matches(site.location_type().as_str())
+    }
+
+    fn team_matches(&self, team: &models::team::Result) -> bool {
+        self.name_matches(&team.display_name, &team.mail) && self.location_matches("Cloud")
+    }
 }
 
-pub async fn remove_item(username: &String, address: &String) -> Result<()> {
-    let mut profile = Profile::get_profile(VProfile::VB365);
+pub async fn org_users(
+    id: &String,
+    address: &String,
+    profile: &Profile,
+    client: &Client,
+    filter: &DirectoryFilter,
+) -> Result<models::user::User, anyhow::Error> {
+    let compiled = CompiledFilter::new(filter)?;
+    let user_string = format!("Organizations/{}/Users", id);
+    let params: Vec<(&str, String)> = filter.name.iter().map(|x| ("userName", x.clone())).collect();
+    let (results, set_id) =
+        fetch_all_pages::<models::user::Result>(&user_string, &params, "users", address, profile, client, filter.max)
+            .await?;
+    let results: Vec<models::user::Result> =
+        results.into_iter().filter(|x| compiled.user_matches(x)).collect();
+    Ok(models::user::User {
+        offset: 0,
+        limit: results.len() as i64,
+        links: Default::default(),
+        results,
+        set_id,
+    })
+}
 
-    let client = login(username, address, &mut profile).await?;
-    let id = get_org_id(address, &profile, &client).await?;
+pub async fn org_groups(
+    id: &String,
+    address: &String,
+    profile: &Profile,
+    client: &Client,
+    filter: &DirectoryFilter,
+) -> Result<models::group::Group, anyhow::Error> {
+    let compiled = CompiledFilter::new(filter)?;
+    let group_string = format!("Organizations/{}/Groups", id);
+    let params: Vec<(&str, String)> = filter.name.iter().map(|x| ("groupName", x.clone())).collect();
+    let (results, set_id) =
+        fetch_all_pages::<models::group::Result>(&group_string, &params, "groups", address, profile, client, filter.max)
+            .await?;
+    let results: Vec<models::group::Result> =
+        results.into_iter().filter(|x| compiled.group_matches(x)).collect();
+    Ok(models::group::Group {
+        links: Default::default(),
+        limit: results.len() as i64,
+        offset: 0,
+        results,
+        set_id,
+    })
+}
 
-    let response = audit_items(&id, address, &profile, &client).await?;
+pub async fn org_sites(
+    id: &String,
+    address: &String,
+    profile: &Profile,
+    client: &Client,
+    filter: &DirectoryFilter,
+) -> Result<models::site::Site, anyhow::Error> {
+    let compiled = CompiledFilter::new(filter)?;
+    let site_string = format!("Organizations/{}/Sites", id);
+    let (results, set_id) =
+        fetch_all_pages::<models::site::Result>(&site_string, &[], "sites", address, profile, client, filter.max)
+            .await?;
+    let results: Vec<models::site::Result> =
+        results.into_iter().filter(|x| compiled.site_matches(x)).collect();
+    Ok(models::site::Site {
+        offset: 0,
+        limit: results.len() as i64,
+        links: Default::default(),
+        results,
+        set_id,
+    })
+}
+
+pub async fn org_teams(
+    id: &String,
+    address: &String,
+    profile: &Profile,
+    client: &Client,
+    filter: &DirectoryFilter,
+) -> Result<models::team::Team, anyhow::Error> {
+    let compiled = CompiledFilter::new(filter)?;
+    let team_string = format!("Organizations/{}/Teams", id);
+    let (results, set_id) =
+        fetch_all_pages::<models::team::Result>(&team_string, &[], "teams", address, profile, client, filter.max)
+            .await?;
+    let results: Vec<models::team::Result> =
+        results.into_iter().filter(|x| compiled.team_matches(x)).collect();
+    Ok(models::team::Team {
+        offset: 0,
+        limit: results.len() as i64,
+        links: Default::default(),
+        results,
+        set_id,
+    })
+}
 
-    let choices = ["Users", "Groups"];
+/// Whether the audit item is a user, group, site or team as selected.
+pub fn is_item_type(item: &AuditItem, item_type: ItemType) -> bool {
+    match item_type {
+        ItemType::Users => item.user.is_some(),
+        ItemType::Groups => item.group.is_some(),
+        ItemType::Sites => item.site.is_some(),
+        ItemType::Teams => item.team.is_some(),
+    }
+}
 
-    let type_selected = Select::new()
-        .with_prompt("Select item to remove")
-        .items(&choices)
-        .interact()?;
+pub async fn add_audit_items(vb365: &Vb356, args: &AddArgs) -> Result<()> {
+    let address = &vb365.address;
+    let mut profile = Profile::get_profile(VProfile::VB365);
 
+    let client = login(vb365, &mut profile).await?;
+    let org_id = get_org_id(address, &profile, &client, args.org.as_deref()).await?;
 
-    let selections: Vec<String> = if type_selected == 0 {
-        response
-            .iter()
-            .filter(|x| x.user.is_some())
-            .map(|x| x.user.as_ref().unwrap().clone())
-            .map(|x| x.display_name)
-            .collect::<Vec<String>>()
+    let item_type = select_item_type(args.item_type, "Select the type of item to add".to_string());
+    let type_string = item_type.label();
+
+    let current = audit_items(&org_id, address, &profile, &client).await?;
+    let audited_ids: Vec<String> = current.iter().map(|x| x.display_name_and_id().1).collect();
+
+    let candidates = if args.pick {
+        pick_audit_items(item_type, &audited_ids, &org_id, address, &profile, &client).await?
     } else {
-        response
-            .iter()
-            .filter(|x| x.group.is_some())
-            .map(|x| x.group.as_ref().unwrap().clone())
-            .map(|x| x.display_name)
-            .collect::<Vec<String>>()
+        let file_name = args.file.as_deref().unwrap_or(item_type.default_file());
+        file_audit_items(item_type, file_name)?
     };
 
-    if selections.is_empty() {
-        println!("No items found");
+    let mut already_audited: Vec<AuditItem> = Vec::new();
+    let mut new_items: Vec<AuditItem> = Vec::new();
+    for item in candidates {
+        let (_, id) = item.display_name_and_id();
+        if audited_ids.contains(&id) {
+            already_audited.push(item);
+        } else if !new_items.iter().any(|x| x.display_name_and_id().1 == id) {
+            new_items.push(item);
+        }
+    }
+
+    for item in already_audited.iter() {
+        println!("  = {} is already audited", item.display_name_and_id().0);
+    }
+    if new_items.is_empty() {
+        println!("No {} to add, {} already audited.", type_string, already_audited.len());
         return Ok(());
     }
 
-    let type_string = if type_selected == 0 {
-        "user"
-    } else {
-        "group"
-    };
+    println!("This will add the following {} to the audit items:", type_string);
+    for item in new_items.iter() {
+        println!("  + {}", item.display_name_and_id().0);
+    }
+    confirm_action(args.yes);
+
+    let rejected = post_audit_items(&new_items, &org_id, address, &profile, &client).await?;
+    for (item, reason) in rejected.iter() {
+        println!("  ! {} was rejected: {}", item.display_name_and_id().0, reason);
+    }
+    println!(
+        "{} already audited, {} newly added, {} rejected.",
+        already_audited.len(),
+        new_items.len() - rejected.len(),
+        rejected.len()
+    );
+
+    if !rejected.is_empty() {
+        return Err(anyhow::anyhow!("{} {} could not be added", rejected.len(), type_string));
+    }
+    Ok(())
+}
+
+/// Posts the items in one request. If VB365 rejects the batch the items that are still missing
+/// are posted one at a time so the rejected ones can be reported with their reason.
+pub async fn post_audit_items<'a>(
+    items: &'a [AuditItem],
+    org_id: &String,
+    address: &String,
+    profile: &Profile,
+    client: &Client,
+) -> Result<Vec<(&'a AuditItem, String)>> {
+    let url = build_url(address, &format!("Organizations/{org_id}/AuditItems"), profile)?;
+
+    let res = client.post(&url).json(items).send().await?;
+    if res.status().is_success() {
+        return Ok(Vec::new());
+    }
+    if items.len() == 1 {
+        return Ok(vec![(&items[0], res.text().await?)]);
+    }
+
+    let audited_ids: Vec<String> = audit_items(org_id, address, profile, client)
+        .await?
+        .iter()
+        .map(|x| x.display_name_and_id().1)
+        .collect();
+    let mut rejected = Vec::new();
+    for item in items {
+        if audited_ids.contains(&item.display_name_and_id().1) {
+            continue;
+        }
+        let res = client.post(&url).json(&[item]).send().await?;
+        if !res.status().is_success() {
+            rejected.push((item, res.text().await?));
+        }
+    }
+    Ok(rejected)
+}
+
+/// Reads the supported users, groups, sites or teams from a file saved by `directory fetch`.
+fn file_audit_items(item_type: ItemType, file_name: &str) -> Result<Vec<AuditItem>> {
+    let mut audit_items: Vec<AuditItem> = Vec::new();
 
-    let ml_prompt = format!("Select {} to remove", type_string);
+    let file_string = fs::read_to_string(file_name)?;
+    match item_type {
+        ItemType::Users => {
+            let file = serde_json::from_str::<models::user::User>(&file_string)?;
+            for user in file.results {
+                if user.type_field.is_supported() {
+                    audit_items.push(AuditItem::from(user));
+                } else {
+                    println!("  ~ {} is skipped, {} users cannot be audited", user.display_name, user.type_field);
+                }
+            }
+        }
+        ItemType::Groups => {
+            let file = serde_json::from_str::<models::group::Group>(&file_string)?;
+            for group in file.results {
+                if group.type_field.is_supported() {
+                    audit_items.push(AuditItem::from(group));
+                } else {
+                    println!("  ~ {} is skipped, {} groups cannot be audited", group.display_name, group.type_field);
+                }
+            }
+        }
+        ItemType::Sites => {
+            let file = serde_json::from_str::<models::site::Site>(&file_string)?;
+            audit_items.extend(file.results.into_iter().map(AuditItem::from));
+        }
+        ItemType::Teams => {
+            let file = serde_json::from_str::<models::team::Team>(&file_string)?;
+            audit_items.extend(file.results.into_iter().map(AuditItem::from));
+        }
+    }
+    Ok(audit_items)
+}
 
-    let multi_select = MultiSelect::new()
-        .with_prompt(ml_prompt)
-        .items(&selections)
-        .interact()?;
+/// Prints how many items of each unsupported type were left out.
+fn report_unsupported<'a>(types: impl Iterator<Item = &'a str>, label: &str) {
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for type_field in types {
+        *counts.entry(type_field).or_default() += 1;
+    }
+    for (type_field, count) in counts {
+        println!("  ~ {} {} skipped, {} {} cannot be audited", count, label, type_field, label);
+    }
+}
 
-    let mut selected_ids: Vec<String> = Vec::new();
+/// Lets the user search the live directory and pick the items to add. Items that are already
+/// audited are marked and start checked, only the new ones are returned.
+async fn pick_audit_items(
+    item_type: ItemType,
+    audited_ids: &[String],
+    org_id: &String,
+    address: &String,
+    profile: &Profile,
+    client: &Client,
+) -> Result<Vec<AuditItem>> {
+    let candidates: Vec<(String, AuditItem)> = match item_type {
+        ItemType::Users => {
+            let (supported, unsupported): (Vec<_>, Vec<_>) =
+                org_users(org_id, address, profile, client, &DirectoryFilter::default())
+                    .await?
+                    .results
+                    .into_iter()
+                    .partition(|x| x.type_field.is_supported());
+            report_unsupported(unsupported.iter().map(|x| x.type_field.as_str()), "users");
+            supported
+                .into_iter()
+                .map(|x| (format!("{} <{}>", x.display_name, x.name), AuditItem::from(x)))
+                .collect()
+        }
+        ItemType::Groups => {
+            let (supported, unsupported): (Vec<_>, Vec<_>) =
+                org_groups(org_id, address, profile, client, &DirectoryFilter::default())
+                    .await?
+                    .results
+                    .into_iter()
+                    .partition(|x| x.type_field.is_supported());
+            report_unsupported(unsupported.iter().map(|x| x.type_field.as_str()), "groups");
+            supported
+                .into_iter()
+                .map(|x| (format!("{} ({})", x.display_name, x.type_field), AuditItem::from(x)))
+                .collect()
+        }
+        ItemType::Sites => org_sites(org_id, address, profile, client, &DirectoryFilter::default())
+            .await?
+            .results
+            .into_iter()
+            .map(|x| (format!("{} <{}>", x.display_name(), x.url), AuditItem::from(x)))
+            .collect(),
+        ItemType::Teams => org_teams(org_id, address, profile, client, &DirectoryFilter::default())
+            .await?
+            .results
+            .into_iter()
+            .map(|x| (format!("{} <{}>", x.display_name, x.mail), AuditItem::from(x)))
+            .collect(),
+    };
+    if candidates.is_empty() {
+        println!("No items found in the directory");
+        return Ok(Vec::new());
+    }
 
-    for (i, v) in response.iter().enumerate() {
-        if multi_select.contains(&i) {
-            if let Some(id) = &v.id {
-                selected_ids.push(id.to_string());
+    let audited: Vec<bool> = candidates
+        .iter()
+        .map(|(_, item)| audited_ids.contains(&item.display_name_and_id().1))
+        .collect();
+    let mut picked = audited.clone();
+    let matcher = SkimMatcherV2::default();
+
+    loop {
+        let search: String = Input::new()
+            .with_prompt("Search (leave empty to show all)")
+            .allow_empty(true)
+            .interact_text()?;
+        let mut matches: Vec<(i64, usize)> = candidates
+            .iter()
+            .enumerate()
+            .filter_map(|(i, (label, _))| {
+                if search.is_empty() {
+                    Some((0, i))
+                } else {
+                    matcher.fuzzy_match(label, &search).map(|score| (score, i))
+                }
+            })
+            .collect();
+        matches.sort_by_key(|x| std::cmp::Reverse(x.0));
+
+        if matches.is_empty() {
+            println!("No matches for {}", search);
+        } else {
+            let labels: Vec<String> = matches
+                .iter()
+                .map(|(_, i)| {
+                    let label = &candidates[*i].0;
+                    if audited[*i] {
+                        format!("{} [audited]", label)
+                    } else {
+                        label.clone()
+                    }
+                })
+                .collect();
+            let defaults: Vec<bool> = matches.iter().map(|(_, i)| picked[*i]).collect();
+            let selection = MultiSelect::new()
+                .with_prompt("Select items to audit, already audited items cannot be removed here")
+                .items(&labels)
+                .defaults(&defaults)
+                .interact()?;
+            for (position, (_, i)) in matches.iter().enumerate() {
+                picked[*i] = audited[*i] || selection.contains(&position);
             }
         }
+
+        if !Confirm::new()
+            .with_prompt("Search for more items?")
+            .default(false)
+            .interact()?
+        {
+            break;
+        }
     }
 
-    if selected_ids.is_empty() {
+    let new_items: Vec<AuditItem> = candidates
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| picked[*i] && !audited[*i])
+        .map(|(_, (_, item))| item)
+        .collect();
+    Ok(new_items)
+}
+
+/// Finds the audit item by directory id, audit item id or short id, then name (UPN, site url or
+/// team mail), then display name.
+fn find_audit_item<'a>(entry: &str, items: &[&'a AuditItem]) -> Result<&'a AuditItem, String> {
+    resolve_any(entry, items, "the audit items", |x| {
+        let (display_name, id) = x.display_name_and_id();
+        let short = short_id(&id);
+        let ids = [id.into(), x.id.clone().unwrap_or_default().into(), short.into()];
+        (ids, x.name().into(), display_name.into())
+    })
+    .copied()
+}
+
+pub async fn remove_item(vb365: &Vb356, args: &RemoveArgs) -> Result<()> {
+    let address = &vb365.address;
+    let mut profile = Profile::get_profile(VProfile::VB365);
+
+    let client = login(vb365, &mut profile).await?;
+    let id = get_org_id(address, &profile, &client, args.org.as_deref()).await?;
+
+    let response = audit_items(&id, address, &profile, &client).await?;
+
+    let selected: Vec<&AuditItem> = if args.items.is_empty() {
+        let type_selected = select_item_type(args.item_type, "Select item to remove".to_string());
+        let candidates: Vec<&AuditItem> = response
+            .iter()
+            .filter(|x| x.id.is_some())
+            .filter(|x| is_item_type(x, type_selected))
+            .collect();
+
+        if candidates.is_empty() {
+            println!("No items found");
+            return Ok(());
+        }
+
+        let selections: Vec<String> = candidates.iter().map(|x| x.display_name_and_id().0).collect();
+
+        let multi_select = MultiSelect::new()
+            .with_prompt(format!("Select {} to remove", type_selected.label()))
+            .items(&selections)
+            .interact()?;
+
+        multi_select.into_iter().map(|i| candidates[i]).collect()
+    } else {
+        let candidates: Vec<&AuditItem> = response
+            .iter()
+            .filter(|x| x.id.is_some())
+            .filter(|x| args.item_type.is_none_or(|item_type| is_item_type(x, item_type)))
+            .collect();
+
+        let mut selected: Vec<&AuditItem> = Vec::new();
+        let mut unresolved: Vec<String> = Vec::new();
+        for entry in args.items.iter() {
+            match find_audit_item(entry, &candidates) {
+                Ok(item) if !selected.contains(&item) => selected.push(item),
+                Ok(_) => {}
+                Err(reason) => unresolved.push(reason),
+            }
+        }
+        if !unresolved.is_empty() {
+            for reason in unresolved.iter() {
+                println!("  ! {}", reason);
+            }
+            return Err(anyhow::anyhow!(
+                "{} items could not be found, nothing was removed",
+                unresolved.len()
+            ));
+        }
+        selected
+    };
+
+    if selected.is_empty() {
         println!("No items selected");
         return Ok(());
     }
-    println!("This will deleted the selected items");
-    confirm_action();
+
+    let item_ids = ItemIds {
+        item_ids: selected.iter().filter_map(|x| x.id.clone()).collect(),
+    };
+
+    println!("This will remove the following audit items:");
+    for item in selected.iter() {
+        let (name, _) = item.display_name_and_id();
+        println!(
+            "  - {:<5} {} ({})",
+            item.type_field.as_str().to_lowercase(),
+            name,
+            item.id.as_deref().unwrap_or_default()
+        );
+    }
+    confirm_action(args.yes);
 
     let url = build_url(
         address,
@@ -231,59 +708,153 @@ pub async fn remove_item(username: &String, address: &String) -> Result<()> {
         &profile,
     )?;
 
-    let item_ids = ItemIds {
-        item_ids: selected_ids.clone(),
-    };
-
     let response = client.post(url).json(&item_ids).send().await?;
 
-    if response.status().is_success() {
-        println!("Items deleted successfully!");
-    } else {
-        println!("Items deletion failed!");
+    if !response.status().is_success() {
         let response_text = response.text().await?;
-        println!("{}", response_text);
+        return Err(anyhow::anyhow!("Removing audit items failed! {}", response_text));
     }
+    println!("{} items removed successfully!", item_ids.item_ids.len());
 
     Ok(())
 }
 
-pub async fn get_users_groups(username: &String, address: &String) -> Result<()> {
+pub async fn get_users_groups(vb365: &Vb356, args: &FetchArgs) -> Result<()> {
+    let address = &vb365.address;
     let mut profile = Profile::get_profile(VProfile::VB365);
 
-    let client = login(username, address, &mut profile).await?;
-    let org_id = get_org_id(address, &profile, &client).await?;
-
-    let selections = &["Users", "Groups"];
-
-    let selection = select_selection(selections, "Select Users or Groups".to_string());
+    let client = login(vb365, &mut profile).await?;
+    let org_id = get_org_id(address, &profile, &client, args.org.as_deref()).await?;
 
-    let type_string = if selection == 0 { "Users" } else { "Groups" };
+    let item_type = select_item_type(args.item_type, "Select the type of item to fetch".to_string());
+    let file_name = args.output.as_deref().unwrap_or(item_type.default_file());
 
     println!(
         "This will get the {} for the organization and save them to a file",
-        type_string
+        item_type.label()
     );
-    confirm_action();
-
-    match selection {
-        0 => {
-            let user_string = format!("Organizations/{}/Users", org_id);
-            let users_url = build_url(address, &user_string, &profile)?;
-            let users: models::user::User = client.get(&users_url).send().await?.json().await?;
-            let file = File::create("users.json")?;
-            serde_json::to_writer_pretty(file, &users)?;
-            print!("Users saved to users.json")
+    confirm_action(args.yes);
+
+    match item_type {
+        ItemType::Users => {
+            let users = org_users(&org_id, address, &profile, &client, &args.filter).await?;
+            serde_json::to_writer_pretty(File::create(file_name)?, &users)?;
+        }
+        ItemType::Groups => {
+            let groups = org_groups(&org_id, address, &profile, &client, &args.filter).await?;
+            serde_json::to_writer_pretty(File::create(file_name)?, &groups)?;
+        }
+        ItemType::Sites => {
+            let sites = org_sites(&org_id, address, &profile, &client, &args.filter).await?;
+            serde_json::to_writer_pretty(File::create(file_name)?, &sites)?;
         }
-        1 => {
-            let group_string = format!("Organizations/{}/Groups", org_id);
-            let groups_url = build_url(address, &group_string, &profile)?;
-            let groups: models::group::Group = client.get(&groups_url).send().await?.json().await?;
-            let file = File::create("groups.json")?;
-            serde_json::to_writer_pretty(file, &groups)?;
-            print!("Groups saved to groups.json")
+        ItemType::Teams => {
+            let teams = org_teams(&org_id, address, &profile, &client, &args.filter).await?;
+            serde_json::to_writer_pretty(File::create(file_name)?, &teams)?;
         }
-        _ => println!("Invalid selection"),
     };
+    println!("Saved the {} to {}", item_type.label(), file_name);
     Ok(())
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{team, user};
+
+    fn user(audit_id: &str, id: &str, name: &str, display_name: &str) -> AuditItem {
+        AuditItem {
+            id: Some(audit_id.to_string()),
+            ..AuditItem::from(user::Result {
+                id: id.to_string(),
+                name: name.to_string(),
+                display_name: display_name.to_string(),
+                ..Default::default()
+            })
+        }
+    }
+
+    fn audited() -> Vec<AuditItem> {
+        let team = AuditItem {
+            id: Some("audit-3".to_string()),
+            ..AuditItem::from(team::Result {
+                id: "org:Team:Team:t3".to_string(),
+                mail: "sales@contoso.com".to_string(),
+                display_name: "Sales".to_string(),
+                ..Default::default()
+            })
+        };
+        vec![
+            user("audit-1", "org:User:User:u1", "adele@contoso.com", "Adele Vance"),
+            user("audit-2", "org:User:User:u2", "adele.v@contoso.com", "Adele Vance"),
+            team,
+        ]
+    }
+
+    fn find(entry: &str) -> Result<String, String> {
+        let items = audited();
+        let items: Vec<&AuditItem> = items.iter().collect();
+        find_audit_item(entry, &items).map(|x| x.id.clone().unwrap_or_default())
+    }
+
+    #[test]
+    fn finds_audit_items_by_any_id() {
+        assert_eq!(find("org:User:User:u2"), Ok("audit-2".to_string()));
+        assert_eq!(find("audit-1"), Ok("audit-1".to_string()));
+        assert_eq!(find("u2"), Ok("audit-2".to_string()));
+    }
+
+    #[test]
+    fn finds_audit_items_by_name() {
+        assert_eq!(find("ADELE@contoso.com"), Ok("audit-1".to_string()));
+        assert_eq!(find("sales@contoso.com"), Ok("audit-3".to_string()));
+        assert_eq!(find("Sales"), Ok("audit-3".to_string()));
+    }
+
+    #[test]
+    fn ambiguous_display_name_is_an_error() {
+        assert_eq!(
+            find("Adele Vance"),
+            Err("Adele Vance matches 2 display names, use the UPN or id".to_string())
+        );
+    }
+
+    #[test]
+    fn unknown_entry_is_an_error() {
+        assert_eq!(find("nobody"), Err("nobody not found in the audit items".to_string()));
+        assert!(find("").is_err());
+    }
+
+    #[test]
+    fn max_smaller_than_a_page_is_fetched_in_one_request() {
+        assert_eq!(next_limit(0, Some(20)), Some(20));
+        assert!(!is_last_page(20, 20, 20));
+        assert_eq!(next_limit(20, Some(20)), None);
+    }
+
+    #[test]
+    fn max_limits_the_last_page() {
+        assert_eq!(next_limit(0, Some(700)), Some(PAGE_SIZE));
+        assert_eq!(next_limit(500, Some(700)), Some(200));
+        assert_eq!(next_limit(0, None), Some(PAGE_SIZE));
+        assert_eq!(next_limit(5000, None), Some(PAGE_SIZE));
+    }
+
+    #[test]
+    fn server_capped_pages_keep_paging() {
+        // Asked for 500, the server only returns 100 at a time.
+        assert!(!is_last_page(100, PAGE_SIZE, 100));
+        assert!(is_last_page(40, PAGE_SIZE, 100));
+    }
+
+    #[test]
+    fn exact_multiple_of_the_limit_ends_on_an_empty_page() {
+        assert!(!is_last_page(PAGE_SIZE, PAGE_SIZE, PAGE_SIZE as i64));
+        assert!(is_last_page(0, PAGE_SIZE, PAGE_SIZE as i64));
+    }
+
+    #[test]
+    fn short_page_is_the_last_without_an_echoed_limit() {
+        assert!(is_last_page(499, PAGE_SIZE, 0));
+        assert!(!is_last_page(PAGE_SIZE, PAGE_SIZE, 0));
+    }
+}
@@ -0,0 +1,287 @@
+use clap::{builder::RangedU64ValueParser, Args, Parser, Subcommand, ValueEnum};
+
+/// Set up and manage VB365 audit notifications.
+///
+/// Runs the interactive menu when no subcommand is given.
+#[derive(Debug, Parser)]
+#[command(author, version, about)]
+pub struct Cli {
+    /// Profile from config.toml to use, defaults to the top level sections
+    #[arg(long, global = true, env = "AUDITOR_PROFILE")]
+    pub profile: Option<String>,
+    /// Skip certificate verification of the VB365 server
+    #[arg(long, global = true)]
+    pub insecure: bool,
+    #[command(subcommand)]
+    pub command: Option<Commands>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Commands {
+    /// Manage the audit items of an organization
+    Audit {
+        #[command(subcommand)]
+        command: AuditCommands,
+    },
+    /// Fetch users, groups, sites and teams from an organization
+    Directory {
+        #[command(subcommand)]
+        command: DirectoryCommands,
+    },
+    /// Manage the audit notification settings
+    Notifications {
+        #[command(subcommand)]
+        command: NotificationCommands,
+    },
+    /// List the server profiles in config.toml
+    Profiles,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum AuditCommands {
+    /// List the current audit items
+    List(ListArgs),
+    /// Add audit items from a users, groups, sites or teams file, or picked from the directory
+    Add(AddArgs),
+    /// Remove audit items
+    Remove(RemoveArgs),
+    /// Show the changes needed to match a manifest
+    Plan(ManifestArgs),
+    /// Add and remove audit items to match a manifest
+    Apply(ManifestArgs),
+    /// Save the audit items to a file for backup or migration
+    Export(ExportArgs),
+    /// Recreate the audit items from an export on this server or org
+    Import(ImportArgs),
+    /// Show which users and groups are not audited
+    Report(ReportArgs),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum DirectoryCommands {
+    /// Save the users, groups, sites or teams of an organization to a file
+    Fetch(FetchArgs),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum NotificationCommands {
+    /// Sign in to Microsoft 365 and apply the notification settings
+    Setup(SetupArgs),
+    /// Show the current notification settings and how they differ from config.toml
+    Show,
+    /// Turn notifications on using the saved sign-in
+    Enable,
+    /// Turn notifications off
+    Disable,
+    /// Change the recipients, sender or subject using the saved sign-in
+    Update(UpdateArgs),
+    /// Send a test email
+    Test,
+}
+
+#[derive(Debug, Default, Args)]
+pub struct SetupArgs {
+    /// Print the sign-in URL and paste the redirect back instead of opening a browser and listening for it
+    #[arg(long)]
+    pub headless: bool,
+}
+
+/// Values not given keep their current setting in VB365.
+#[derive(Debug, Default, Args)]
+pub struct UpdateArgs {
+    /// Recipient address
+    #[arg(long)]
+    pub to: Option<String>,
+    /// Sender address
+    #[arg(long)]
+    pub from: Option<String>,
+    /// Email subject
+    #[arg(long)]
+    pub subject: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ItemType {
+    Users,
+    Groups,
+    Sites,
+    Teams,
+}
+
+impl ItemType {
+    pub fn default_file(&self) -> &'static str {
+        match self {
+            ItemType::Users => "users.json",
+            ItemType::Groups => "groups.json",
+            ItemType::Sites => "sites.json",
+            ItemType::Teams => "teams.json",
+        }
+    }
+
+    /// Lower case name used in messages, e.g. "users".
+    pub fn label(&self) -> &'static str {
+        match self {
+            ItemType::Users => "users",
+            ItemType::Groups => "groups",
+            ItemType::Sites => "sites",
+            ItemType::Teams => "teams",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    #[default]
+    Table,
+    Json,
+    #[value(name = "jsonl")]
+    JsonLines,
+    Csv,
+    Yaml,
+}
+
+#[derive(Debug, Default, Args)]
+pub struct ListArgs {
+    /// Organization name, office name or id, prompts when there is more than one
+    #[arg(long)]
+    pub org: Option<String>,
+    /// Run for every organization on the server
+    #[arg(long, conflicts_with = "org")]
+    pub all_orgs: bool,
+    /// Output format
+    #[arg(long, value_enum, default_value_t)]
+    pub format: OutputFormat,
+}
+
+#[derive(Debug, Default, Args)]
+pub struct ReportArgs {
+    /// Organization name, office name or id, prompts when there is more than one
+    #[arg(long)]
+    pub org: Option<String>,
+    /// Run for every organization on the server
+    #[arg(long, conflicts_with = "org")]
+    pub all_orgs: bool,
+    /// Output format, csv and jsonl list the uncovered items only
+    #[arg(long, value_enum, default_value_t)]
+    pub format: OutputFormat,
+    /// Manifest of items that must be audited, fails when any of them are not
+    #[arg(long, conflicts_with = "all_orgs")]
+    pub must_audit: Option<String>,
+}
+
+#[derive(Debug, Default, Args)]
+pub struct AddArgs {
+    /// Organization name, office name or id, prompts when there is more than one
+    #[arg(long)]
+    pub org: Option<String>,
+    /// Type of item to add, prompts when not set
+    #[arg(long = "type", value_enum)]
+    pub item_type: Option<ItemType>,
+    /// File to read the items from, defaults to users.json, groups.json, sites.json or teams.json
+    #[arg(long)]
+    pub file: Option<String>,
+    /// Search the organization directory and pick the items instead of reading a file
+    #[arg(long, conflicts_with = "file")]
+    pub pick: bool,
+    /// Do not ask for confirmation
+    #[arg(long, short)]
+    pub yes: bool,
+}
+
+#[derive(Debug, Default, Args)]
+pub struct RemoveArgs {
+    /// Items to remove by display name, UPN, directory id or short id, prompts when not set
+    pub items: Vec<String>,
+    /// Organization name, office name or id, prompts when there is more than one
+    #[arg(long)]
+    pub org: Option<String>,
+    /// Type of item to remove, prompts when no items are given
+    #[arg(long = "type", value_enum)]
+    pub item_type: Option<ItemType>,
+    /// Do not ask for confirmation
+    #[arg(long, short)]
+    pub yes: bool,
+}
+
+#[derive(Debug, Default, Args)]
+pub struct ManifestArgs {
+    /// Manifest of the users and groups to audit, TOML or YAML
+    #[arg(long, default_value = "audit.toml")]
+    pub manifest: String,
+    /// Organization name, office name or id, overrides the org set in the manifest
+    #[arg(long)]
+    pub org: Option<String>,
+    /// Do not ask for confirmation
+    #[arg(long, short)]
+    pub yes: bool,
+}
+
+#[derive(Debug, Default, Args)]
+pub struct ExportArgs {
+    /// Organization name, office name or id, prompts when there is more than one
+    #[arg(long)]
+    pub org: Option<String>,
+    /// Run for every organization on the server
+    #[arg(long, conflicts_with = "org")]
+    pub all_orgs: bool,
+    /// File to save the export to
+    #[arg(long, default_value = "audit-export.json")]
+    pub output: String,
+}
+
+#[derive(Debug, Default, Args)]
+pub struct ImportArgs {
+    /// Export file to read the audit items from
+    #[arg(long, default_value = "audit-export.json")]
+    pub file: String,
+    /// Organization name, office name or id, defaults to the org the items were exported from,
+    /// only allowed when the export has one org
+    #[arg(long)]
+    pub org: Option<String>,
+    /// Do not ask for confirmation
+    #[arg(long, short)]
+    pub yes: bool,
+}
+
+#[derive(Debug, Default, Args)]
+pub struct FetchArgs {
+    /// Type of item to fetch, prompts when not set
+    #[arg(value_enum)]
+    pub item_type: Option<ItemType>,
+    /// Organization name, office name or id, prompts when there is more than one
+    #[arg(long)]
+    pub org: Option<String>,
+    /// File to save the items to, defaults to users.json, groups.json, sites.json or teams.json
+    #[arg(long)]
+    pub output: Option<String>,
+    #[command(flatten)]
+    pub filter: DirectoryFilter,
+    /// Do not ask for confirmation
+    #[arg(long, short)]
+    pub yes: bool,
+}
+
+#[derive(Debug, Default, Args)]
+pub struct DirectoryFilter {
+    /// Only keep items whose display name or UPN contains this text, searched on the server
+    #[arg(long)]
+    pub name: Option<String>,
+    /// Only keep items whose display name or UPN matches this regex
+    #[arg(long)]
+    pub name_regex: Option<String>,
+    /// Only keep users of these types, e.g. User,Shared,Public
+    #[arg(long, value_delimiter = ',')]
+    pub user_type: Vec<String>,
+    /// Only keep groups of these types, e.g. Office365,Security
+    #[arg(long, value_delimiter = ',')]
+    pub group_type: Vec<String>,
+    /// Only keep items with these location types, e.g. Cloud,OnPremises,Hybrid
+    #[arg(long, value_delimiter = ',')]
+    pub location_type: Vec<String>,
+    /// Only keep groups whose manager contains this text
+    #[arg(long)]
+    pub managed_by: Option<String>,
+    /// Stop after fetching this many items
+    #[arg(long, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    pub max: Option<usize>,
+}
@@ -1,5 +1,7 @@
 use dialoguer::{Select, theme::ColorfulTheme, console::Term};
 
+use crate::cli::ItemType;
+
 pub fn select_selection(selections: &[&str], prompt: String) -> usize {
     let selection = Select::with_theme(&ColorfulTheme::default())
         .with_prompt(&prompt)
@@ -9,4 +11,19 @@ pub fn select_selection(selections: &[&str], prompt: String) -> usize {
         .unwrap()
         .unwrap();
     selection
-}
\ No newline at end of file
+}
+
+pub fn select_item_type(item_type: Option<ItemType>, prompt: String) -> ItemType {
+    match item_type {
+        Some(item_type) => item_type,
+        None => {
+            let selections = &["Users", "Groups", "Sites", "Teams"];
+            match select_selection(selections, prompt) {
+                0 => ItemType::Users,
+                1 => ItemType::Groups,
+                2 => ItemType::Sites,
+                _ => ItemType::Teams,
+            }
+        }
+    }
+}
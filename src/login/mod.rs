@@ -1,110 +1,239 @@
-use std::{fs, io::Write};
+use std::{env, time::Duration as StdDuration};
 
 use chrono::{Local, DateTime, Utc, Duration};
 use dialoguer::{Select, theme::ColorfulTheme, console::Term};
-use reqwest::Client;
-use vauth::{Profile, build_auth_headers, VClientBuilder, build_url};
+use reqwest::{header::ACCEPT, Client};
+use serde::Serialize;
+use vauth::{Profile, build_auth_headers, build_url, Creds, LoginResponse, RefreshCreds};
 use anyhow::Result;
-use crate::models::{config::LoginExtended, org::OrgItem};
+use crate::{models::{config::{LoginExtended, Vb356}, org::OrgItem}, tls::client_builder, token::{token_store, TokenStore}};
 
-pub async fn login(username: &String, address: &String, profile: &mut Profile) -> Result<Client> {
-    let token_file = fs::read_to_string("token.json");
+/// Tokens this close to expiring are refreshed before they are used.
+const REFRESH_MARGIN_SECONDS: i64 = 300;
 
-    match token_file {
-        Ok(token_file) => {
-            let token_struct: LoginExtended = serde_json::from_str(&token_file)?;
+pub async fn login(vb365: &Vb356, profile: &mut Profile) -> Result<Client> {
+    if vb365.insecure {
+        eprintln!("WARNING: certificate verification is disabled, the connection to VB365 is not protected.");
+    }
+    let mut store = token_store(vb365)?;
+    let saved_token = store
+        .load()
+        .unwrap_or_else(|e| {
+            eprintln!("Unable to read the saved token: {}", e);
+            None
+        })
+        .filter(|x| {
+            let issued_here = x.address == vb365.address && x.username == vb365.username;
+            if !issued_here {
+                eprintln!("Saved token was issued for a different server or user, ignoring it.");
+            }
+            issued_here
+        });
+
+    match saved_token {
+        Some(token_struct) => {
             let now = Local::now();
             let token_expires_on = DateTime::parse_from_rfc3339(&token_struct.expires_on)?;
 
-            if now > token_expires_on {
-                login_full(
-                    address,
-                    username,
-                    profile,
-                    "Token expired, logging in again.".to_string(),
-                )
-                .await
+            if now + Duration::seconds(REFRESH_MARGIN_SECONDS) > token_expires_on {
+                eprintln!("Token expired or about to expire, refreshing it.");
+                match login_refresh(vb365, &token_struct.refresh_token, profile, store.as_mut()).await {
+                    Ok(client) => Ok(client),
+                    Err(e) => {
+                        login_full(
+                            vb365,
+                            profile,
+                            store.as_mut(),
+                            format!("Token refresh failed ({}), logging in again.", e),
+                        )
+                        .await
+                    }
+                }
             } else {
-                println!("Token is still valid, using it.");
-                let auth_headers = build_auth_headers(&token_struct.access_token, profile);
-
-                let client = reqwest::Client::builder()
-                    .danger_accept_invalid_certs(true)
-                    .default_headers(auth_headers)
-                    .build()?;
-
-                Ok(client)
+                eprintln!("Token is still valid, using it.");
+                token_client(vb365, &token_struct.access_token, profile)
             }
         }
-        Err(_) => {
+        None => {
             login_full(
-                address,
-                username,
+                vb365,
                 profile,
-                "No token file, logging in.".to_string(),
+                store.as_mut(),
+                "No saved token, logging in.".to_string(),
             )
             .await
         }
     }
 }
 
+fn token_client(vb365: &Vb356, access_token: &String, profile: &Profile) -> Result<Client> {
+    let auth_headers = build_auth_headers(access_token, profile);
+
+    let client = client_builder(vb365)?
+        .default_headers(auth_headers)
+        .build()?;
+
+    Ok(client)
+}
+
+/// Posts the grant to the VB365 token endpoint.
+async fn request_token<T: Serialize>(vb365: &Vb356, profile: &Profile, grant: &T) -> Result<LoginResponse> {
+    let auth_url = format!("https://{}{}", vb365.address, profile.url);
+
+    let client = client_builder(vb365)?
+        .timeout(StdDuration::from_secs(60))
+        .build()?;
+    let mut request = client
+        .post(auth_url)
+        .header(ACCEPT, "application/json")
+        .form(grant);
+    if !profile.x_api_version.is_empty() {
+        request = request.header("X-Api-Version", &profile.x_api_version);
+    }
+    let response = request.send().await?;
+
+    if !response.status().is_success() {
+        return Err(anyhow::anyhow!("status code {}", response.status()));
+    }
+    Ok(response.json().await?)
+}
+
+/// Exchanges the saved refresh token for a new access token using the refresh token grant.
+pub async fn login_refresh(
+    vb365: &Vb356,
+    refresh_token: &str,
+    profile: &Profile,
+    store: &mut dyn TokenStore,
+) -> Result<Client> {
+    if refresh_token.is_empty() {
+        return Err(anyhow::anyhow!("no refresh token saved"));
+    }
+    let creds = RefreshCreds {
+        grant_type: "refresh_token".to_string(),
+        refresh_token: refresh_token.to_string(),
+    };
+    let login_response = request_token(vb365, profile, &creds).await?;
+    let client = token_client(vb365, &login_response.access_token, profile)?;
+    save_token(vb365, store, login_response)?;
+    eprintln!("Token refreshed.");
+    Ok(client)
+}
+
 pub async fn login_full(
-    address: &String,
-    username: &String,
-    profile: &mut Profile,
+    vb365: &Vb356,
+    profile: &Profile,
+    store: &mut dyn TokenStore,
     reason: String,
 ) -> Result<Client> {
-    println!("{}", reason);
-    let (client, login_response) = VClientBuilder::new(address, username.to_string())
-        .insecure()
-        .timeout(60)
-        .build(profile)
+    eprintln!("{}", reason);
+    let password = env::var("VEEAM_API_PASSWORD")
+        .map_err(|_| anyhow::anyhow!("The VEEAM_API_PASSWORD environmental variable is missing"))?;
+    if password.is_empty() {
+        return Err(anyhow::anyhow!("The VEEAM_API_PASSWORD environmental variable is empty"));
+    }
+    let creds = Creds::new(vb365.username.clone(), password);
+    let login_response = request_token(vb365, profile, &creds)
         .await
-        .unwrap();
-    let now = Utc::now();
-    let expires_on = now + Duration::seconds(login_response.expires_in as i64);
-    let expires_on_string = expires_on.to_rfc3339();
-    save_token_file(login_response, expires_on_string)?;
+        .map_err(|e| anyhow::anyhow!("Login failed: {}", e))?;
+    let client = token_client(vb365, &login_response.access_token, profile)?;
+    save_token(vb365, store, login_response)?;
     Ok(client)
 }
 
-fn save_token_file(
-    login_response: vauth::LoginResponse,
-    expires_on_string: String,
+fn save_token(
+    vb365: &Vb356,
+    store: &mut dyn TokenStore,
+    login_response: LoginResponse,
 ) -> Result<(), anyhow::Error> {
+    let now = Utc::now();
+    let expires_on = now + Duration::seconds(login_response.expires_in as i64);
+    let expires_on_string = expires_on.to_rfc3339();
     let login_expended = LoginExtended {
         access_token: login_response.access_token,
         token_type: login_response.token_type,
         refresh_token: login_response.refresh_token,
         expires_in: login_response.expires_in,
         expires_on: expires_on_string,
+        address: vb365.address.clone(),
+        username: vb365.username.clone(),
     };
-    let mut json_file = fs::File::create("token.json")?;
-    let token_string = serde_json::to_string_pretty(&login_expended)?;
-    json_file.write_all(token_string.as_bytes())?;
-    Ok(())
+    store.save(&login_expended)
 }
 
 pub async fn get_org_id(
     address: &String,
     profile: &Profile,
     client: &Client,
+    org: Option<&str>,
 ) -> Result<String, anyhow::Error> {
+    Ok(get_org(address, profile, client, org).await?.id)
+}
+
+/// Every organization on the server, errors when there are none.
+pub async fn get_orgs(
+    address: &String,
+    profile: &Profile,
+    client: &Client,
+) -> Result<Vec<OrgItem>, anyhow::Error> {
     let org_url = build_url(address, &"Organizations".to_string(), profile)?;
-    let response: Vec<OrgItem> = client.get(&org_url).send().await?.json().await?;
-    let id: String = if response.len() > 1 {
-        let selections: Vec<String> = response.iter().map(|x| x.name.clone()).collect();
-        let selection = Select::with_theme(&ColorfulTheme::default())
+    let response = client.get(&org_url).send().await?;
+    if !response.status().is_success() {
+        let response_text = response.text().await?;
+        return Err(anyhow::anyhow!("Failed to get the organizations: {}", response_text));
+    }
+    let orgs: Vec<OrgItem> = response.json().await?;
+    if orgs.is_empty() {
+        return Err(anyhow::anyhow!("No organizations found on {}, add one in VB365 first", address));
+    }
+    Ok(orgs)
+}
+
+/// The organization by name, office name or id, prompts when none is given and there is more
+/// than one.
+pub async fn get_org(
+    address: &String,
+    profile: &Profile,
+    client: &Client,
+    org: Option<&str>,
+) -> Result<OrgItem, anyhow::Error> {
+    let mut response = get_orgs(address, profile, client).await?;
+    if let Some(org) = org {
+        let found = response.iter().position(|x| {
+            x.id == org || x.name.eq_ignore_ascii_case(org) || x.office_name.eq_ignore_ascii_case(org)
+        });
+        return match found {
+            Some(index) => Ok(response.swap_remove(index)),
+            None => Err(anyhow::anyhow!("Organization {} not found", org)),
+        };
+    }
+    let index = if response.len() > 1 {
+        let selections: Vec<String> = response
+            .iter()
+            .map(|x| format!("{} ({})", x.name, x.office_name))
+            .collect();
+        Select::with_theme(&ColorfulTheme::default())
             .with_prompt("Select Organization")
             .default(0)
             .items(&selections[..])
-            .interact_on_opt(&Term::stderr())
-            .unwrap()
-            .unwrap();
-
-        response[selection].id.clone()
+            .interact_on_opt(&Term::stderr())?
+            .ok_or_else(|| anyhow::anyhow!("No organization selected"))?
     } else {
-        response[0].id.clone()
+        0
     };
-    Ok(id)
-}
\ No newline at end of file
+    Ok(response.swap_remove(index))
+}
+
+/// Every organization with `all_orgs`, otherwise the one picked by `get_org`.
+pub async fn select_orgs(
+    address: &String,
+    profile: &Profile,
+    client: &Client,
+    org: Option<&str>,
+    all_orgs: bool,
+) -> Result<Vec<OrgItem>, anyhow::Error> {
+    if all_orgs {
+        get_orgs(address, profile, client).await
+    } else {
+        Ok(vec![get_org(address, profile, client, org).await?])
+    }
+}
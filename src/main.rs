@@ -1,16 +1,29 @@
 use std::fs;
 
+use clap::Parser;
 use dialoguer::{console::Term, theme::ColorfulTheme, Select};
 mod setup;
 mod audit;
+mod cli;
 mod login;
 mod models;
 mod tcplistener;
+mod tls;
+mod token;
 mod helpers;
 mod notifications;
-use notifications::{setup_notifications, sent_test_email};
-use login::login;
+mod output;
+mod plan;
+mod profiles;
+mod report;
+mod transfer;
+use notifications::{setup_notifications, show_notifications, set_notifications_enabled, update_notifications, sent_test_email};
 use audit::{get_audit_items, add_audit_items, remove_item, get_users_groups};
+use plan::{plan_audit_items, apply_audit_items};
+use transfer::{export_audit_items, import_audit_items};
+use report::coverage_report;
+use profiles::list_profiles;
+use cli::{Cli, Commands, AuditCommands, AddArgs, DirectoryCommands, NotificationCommands, SetupArgs};
 
 
 use anyhow::Result;
@@ -18,18 +31,65 @@ use models::config::Config;
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    let cli = Cli::parse();
+
     let file_string = fs::read_to_string("config.toml")?;
     let config: Config = toml::from_str(&file_string)?;
 
-    let username = config.vb365.username.clone();
-    let address = config.vb365.address.clone();
+    if let Some(Commands::Profiles) = cli.command {
+        list_profiles(&config);
+        return Ok(());
+    }
+
+    let mut config = config.select_profile(cli.profile.as_deref())?;
+    if cli.insecure {
+        config.vb365.insecure = true;
+    }
 
+    let vb365 = config.vb365.clone();
+
+    let command = match cli.command {
+        Some(command) => command,
+        None => select_command(),
+    };
+
+    match command {
+        Commands::Audit { command } => match command {
+            AuditCommands::List(args) => get_audit_items(&vb365, &args).await?,
+            AuditCommands::Add(args) => add_audit_items(&vb365, &args).await?,
+            AuditCommands::Remove(args) => remove_item(&vb365, &args).await?,
+            AuditCommands::Plan(args) => plan_audit_items(&vb365, &args).await?,
+            AuditCommands::Apply(args) => apply_audit_items(&vb365, &args).await?,
+            AuditCommands::Export(args) => export_audit_items(&vb365, &args).await?,
+            AuditCommands::Import(args) => import_audit_items(&vb365, &args).await?,
+            AuditCommands::Report(args) => coverage_report(&vb365, &args).await?,
+        },
+        Commands::Directory { command } => match command {
+            DirectoryCommands::Fetch(args) => get_users_groups(&vb365, &args).await?,
+        },
+        Commands::Notifications { command } => match command {
+            NotificationCommands::Setup(args) => setup_notifications(config, &args).await?,
+            NotificationCommands::Show => show_notifications(&config).await?,
+            NotificationCommands::Enable => set_notifications_enabled(&config, true).await?,
+            NotificationCommands::Disable => set_notifications_enabled(&config, false).await?,
+            NotificationCommands::Update(args) => update_notifications(&config, &args).await?,
+            NotificationCommands::Test => sent_test_email(&vb365).await?,
+        },
+        Commands::Profiles => list_profiles(&config),
+    }
+
+    Ok(())
+}
+
+fn select_command() -> Commands {
     let selections = &[
         "Get Audit Items",
         "Add Audit items",
+        "Pick Audit items from directory",
         "Remove Audit items",
         "Get Users/Groups",
         "Setup Notifications",
+        "Show Notification Settings",
         "Send Test Email",
     ];
     let selection = Select::with_theme(&ColorfulTheme::default())
@@ -41,14 +101,13 @@ async fn main() -> Result<()> {
         .unwrap();
 
     match selection {
-        0 => get_audit_items(&username, &address).await?,
-        1 => add_audit_items(&username, &address).await?,
-        2 => remove_item(&username, &address).await?,
-        3 => get_users_groups(&username, &address).await?,
-        4 => setup_notifications(&username, &address, config).await?,
-        5 => sent_test_email(&username, &address).await?,
-        _ => println!("Invalid selection"),
+        0 => Commands::Audit { command: AuditCommands::List(Default::default()) },
+        1 => Commands::Audit { command: AuditCommands::Add(Default::default()) },
+        2 => Commands::Audit { command: AuditCommands::Add(AddArgs { pick: true, ..Default::default() }) },
+        3 => Commands::Audit { command: AuditCommands::Remove(Default::default()) },
+        4 => Commands::Directory { command: DirectoryCommands::Fetch(Default::default()) },
+        5 => Commands::Notifications { command: NotificationCommands::Setup(SetupArgs::default()) },
+        6 => Commands::Notifications { command: NotificationCommands::Show },
+        _ => Commands::Notifications { command: NotificationCommands::Test },
     }
-
-    Ok(())
 }
@@ -2,6 +2,9 @@ use serde::Deserialize;
 use serde::Serialize;
 
 use super::group::Result as GroupResult;
+use super::kind::{GroupType, ItemKind, LocationType};
+use super::site::Result as SiteResult;
+use super::team::Result as TeamResult;
 use super::user::Result as UserResult;
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -10,18 +13,22 @@ pub struct AuditItem {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub id: Option<String>,
     #[serde(rename = "type")]
-    pub type_field: String,
+    pub type_field: ItemKind,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub user: Option<User2>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub group: Option<Group>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub site: Option<Site>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub team: Option<Team>,
 }
 
 impl From<UserResult> for AuditItem {
     fn from(user: UserResult) -> Self {
         AuditItem {
             id: None,
-            type_field: user.type_field,
+            type_field: ItemKind::User,
             user: Some(User2 {
                 links: None,
                 display_name: user.display_name,
@@ -30,6 +37,8 @@ impl From<UserResult> for AuditItem {
                 name: user.name,
             }),
             group: None,
+            site: None,
+            team: None,
         }
     }
 }
@@ -38,36 +47,160 @@ impl From<GroupResult> for AuditItem {
     fn from(group: GroupResult) -> Self {
         AuditItem {
             id: None,
-            type_field: "Group".to_string(),
+            type_field: ItemKind::Group,
             user: None,
             group: Some(Group {
-                display_name: group.display_name.clone(),
+                display_name: group.display_name,
                 id: group.id,
-                type_field: "Office365".to_string(),
-                location_type: "Cloud".to_string(),
-                name: group.display_name,
+                type_field: group.type_field,
+                location_type: group.location_type,
+                name: group.name,
             }),
+            site: None,
+            team: None,
         }
     }
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct Links {
-    pub organization: Organization,
-    pub user: User,
+impl From<SiteResult> for AuditItem {
+    fn from(site: SiteResult) -> Self {
+        AuditItem {
+            id: None,
+            type_field: ItemKind::Site,
+            user: None,
+            group: None,
+            site: Some(Site {
+                id: site.id,
+                url: site.url,
+                name: site.name,
+                title: site.title,
+                is_cloud: site.is_cloud,
+                is_personal: site.is_personal,
+            }),
+            team: None,
+        }
+    }
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+impl From<TeamResult> for AuditItem {
+    fn from(team: TeamResult) -> Self {
+        AuditItem {
+            id: None,
+            type_field: ItemKind::Team,
+            user: None,
+            group: None,
+            site: None,
+            team: Some(Team {
+                id: team.id,
+                display_name: team.display_name,
+                description: team.description,
+                mail: team.mail,
+            }),
+        }
+    }
+}
+
+impl AuditItem {
+    /// The display name and directory id of the user, group, site or team being audited.
+    pub fn display_name_and_id(&self) -> (String, String) {
+        if let Some(user) = &self.user {
+            (user.display_name.clone(), user.id.clone())
+        } else if let Some(group) = &self.group {
+            (group.display_name.clone(), group.id.clone())
+        } else if let Some(site) = &self.site {
+            (site.display_name().to_string(), site.id.clone())
+        } else if let Some(team) = &self.team {
+            (team.display_name.clone(), team.id.clone())
+        } else {
+            (String::new(), String::new())
+        }
+    }
+
+    /// The name (UPN) of a user or group, the url of a site or the mail of a team.
+    pub fn name(&self) -> String {
+        if let Some(user) = &self.user {
+            user.name.clone()
+        } else if let Some(group) = &self.group {
+            group.name.clone()
+        } else if let Some(site) = &self.site {
+            site.url.clone()
+        } else if let Some(team) = &self.team {
+            team.mail.clone()
+        } else {
+            String::new()
+        }
+    }
+}
+
+/// Splits the short id out of a directory id such as `<org>:<kind>:<type>:<short id>`.
+pub fn short_id(id: &str) -> String {
+    id.split(':').nth(3).unwrap_or(id).to_owned()
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize)]
 #[serde(rename_all = "camelCase")]
-pub struct Organization {
-    pub href: String,
+pub struct AuditItemRow {
+    pub org: String,
+    pub display_name: String,
+    pub short_id: String,
+    #[serde(rename = "type")]
+    pub type_field: ItemKind,
+    pub item: AuditItem,
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+impl From<AuditItem> for AuditItemRow {
+    fn from(item: AuditItem) -> Self {
+        let (display_name, id) = item.display_name_and_id();
+        AuditItemRow {
+            org: String::new(),
+            display_name,
+            short_id: short_id(&id),
+            type_field: item.type_field.clone(),
+            item,
+        }
+    }
+}
+
+/// Flat form of `AuditItemRow` for CSV output.
+#[derive(Default, Debug, Clone, PartialEq, Serialize)]
 #[serde(rename_all = "camelCase")]
-pub struct User {
-    pub href: String,
+pub struct AuditItemRecord {
+    pub org: String,
+    pub display_name: String,
+    pub short_id: String,
+    #[serde(rename = "type")]
+    pub type_field: ItemKind,
+    pub id: Option<String>,
+    pub directory_id: String,
+    pub name: String,
+    pub location_type: LocationType,
+}
+
+impl From<&AuditItemRow> for AuditItemRecord {
+    fn from(row: &AuditItemRow) -> Self {
+        let (directory_id, name, location_type) = if let Some(user) = &row.item.user {
+            (user.id.clone(), user.name.clone(), user.location_type.clone())
+        } else if let Some(group) = &row.item.group {
+            (group.id.clone(), group.name.clone(), group.location_type.clone())
+        } else if let Some(site) = &row.item.site {
+            let location_type = if site.is_cloud { LocationType::Cloud } else { LocationType::OnPremises };
+            (site.id.clone(), site.url.clone(), location_type)
+        } else if let Some(team) = &row.item.team {
+            (team.id.clone(), team.mail.clone(), LocationType::Cloud)
+        } else {
+            (String::new(), String::new(), LocationType::Unknown(String::new()))
+        };
+        AuditItemRecord {
+            org: row.org.clone(),
+            display_name: row.display_name.clone(),
+            short_id: row.short_id.clone(),
+            type_field: row.type_field.clone(),
+            id: row.item.id.clone(),
+            directory_id,
+            name,
+            location_type,
+        }
+    }
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -77,7 +210,7 @@ pub struct User2 {
     pub links: Option<Links2>,
     pub display_name: String,
     pub id: String,
-    pub location_type: String,
+    pub location_type: LocationType,
     pub name: String,
 }
 
@@ -87,11 +220,47 @@ pub struct Group {
     pub display_name: String,
     pub id: String,
     #[serde(rename = "type")]
-    pub type_field: String,
-    pub location_type: String,
+    pub type_field: GroupType,
+    pub location_type: LocationType,
     pub name: String,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Links2 {}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Site {
+    pub id: String,
+    pub url: String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub is_cloud: bool,
+    #[serde(default)]
+    pub is_personal: bool,
+}
+
+impl Site {
+    pub fn display_name(&self) -> &str {
+        if self.title.is_empty() {
+            &self.url
+        } else {
+            &self.title
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Team {
+    pub id: String,
+    pub display_name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub mail: String,
+}
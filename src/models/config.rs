@@ -1,11 +1,52 @@
+use std::collections::BTreeMap;
+
 use serde::Deserialize;
 use serde::Serialize;
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Config {
+    #[serde(default)]
     pub azure: Azure,
+    #[serde(default)]
     pub notification: Notification,
+    #[serde(default)]
     pub vb365: Vb356,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, ServerProfile>,
+}
+
+/// A named VB365 server, the azure and notification sections fall back to the top level ones.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ServerProfile {
+    pub azure: Option<Azure>,
+    pub notification: Option<Notification>,
+    pub vb365: Vb356,
+}
+
+impl Config {
+    /// Returns the config for the named profile, or the top level sections when no name is given.
+    pub fn select_profile(self, name: Option<&str>) -> Result<Config, anyhow::Error> {
+        let name = match name {
+            Some(name) => name,
+            None if self.vb365.address.is_empty() && !self.profiles.is_empty() => {
+                return Err(anyhow::anyhow!(
+                    "There is no [vb365] section, select one of the profiles with --profile"
+                ))
+            }
+            None => return Ok(self),
+        };
+        let profile = self
+            .profiles
+            .get(name)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("Profile {} not found in config.toml", name))?;
+        Ok(Config {
+            azure: profile.azure.unwrap_or(self.azure),
+            notification: profile.notification.unwrap_or(self.notification),
+            vb365: profile.vb365,
+            profiles: BTreeMap::new(),
+        })
+    }
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -14,6 +55,10 @@ pub struct Azure {
     pub tenant_id: Option<String>,
     pub client_id: Option<String>,
     pub client_secret : Option<String>,
+    /// Seconds to wait for the sign-in callback, defaults to five minutes.
+    pub callback_timeout: Option<u64>,
+    /// Address the callback listener binds, with an optional port, defaults to 127.0.0.1.
+    pub bind_address: Option<String>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -29,6 +74,22 @@ pub struct Notification {
 pub struct Vb356 {
     pub username: String,
     pub address: String,
+    #[serde(default)]
+    pub token_store: TokenStoreKind,
+    pub ca_bundle: Option<String>,
+    pub pin_sha256: Option<String>,
+    #[serde(default)]
+    pub insecure: bool,
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenStoreKind {
+    #[default]
+    File,
+    Encrypted,
+    Keyring,
+    Memory,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -38,4 +99,81 @@ pub struct LoginExtended {
     pub refresh_token: String,
     pub expires_in: i32,
     pub expires_on: String,
+    #[serde(default)]
+    pub address: String,
+    #[serde(default)]
+    pub username: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EncryptedToken {
+    pub version: u32,
+    pub salt: String,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        toml::from_str(
+            r#"
+            [azure]
+            redirect_url = "http://localhost"
+
+            [notification]
+            username = "top"
+            from = "top@contoso.com"
+            to = "top@contoso.com"
+            subject = "top"
+            user_id = "1"
+
+            [vb365]
+            username = "admin"
+            address = "vb365.contoso.com"
+
+            [profiles.lab.vb365]
+            username = "lab-admin"
+            address = "lab.contoso.com"
+
+            [profiles.lab.azure]
+            redirect_url = "http://localhost:8080"
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn no_profile_uses_the_top_level_sections() {
+        let config = config();
+        assert_eq!(config.clone().select_profile(None).unwrap(), config);
+    }
+
+    #[test]
+    fn profile_falls_back_to_the_top_level_sections() {
+        let config = config();
+        let lab = config.clone().select_profile(Some("lab")).unwrap();
+        assert_eq!(lab.vb365.address, "lab.contoso.com");
+        assert_eq!(lab.azure.redirect_url, "http://localhost:8080");
+        // The lab profile has no [notification] of its own.
+        assert_eq!(lab.notification, config.notification);
+        assert!(lab.profiles.is_empty());
+    }
+
+    #[test]
+    fn unknown_profile_is_an_error() {
+        let error = config().select_profile(Some("prod")).unwrap_err();
+        assert_eq!(error.to_string(), "Profile prod not found in config.toml");
+    }
+
+    #[test]
+    fn profiles_without_a_top_level_vb365_need_a_profile() {
+        let mut config = config();
+        config.vb365 = Vb356::default();
+        let error = config.clone().select_profile(None).unwrap_err();
+        assert!(error.to_string().contains("select one of the profiles with --profile"));
+        assert_eq!(config.select_profile(Some("lab")).unwrap().vb365.address, "lab.contoso.com");
+    }
 }
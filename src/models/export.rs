@@ -0,0 +1,59 @@
+use serde::Deserialize;
+use serde::Serialize;
+
+use super::audit::AuditItem;
+
+/// Current version of the export file format.
+pub const EXPORT_VERSION: u32 = 2;
+
+/// Audit items saved by `audit export`, the items keep the directory ids and display names so
+/// they can be matched again on another server.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditExport {
+    pub version: u32,
+    pub exported_on: String,
+    pub server: String,
+    pub orgs: Vec<OrgExport>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrgExport {
+    pub org: String,
+    pub org_id: String,
+    pub items: Vec<AuditItem>,
+}
+
+/// Read first to pick the format of the rest of the file.
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+pub struct ExportVersion {
+    pub version: u32,
+}
+
+/// Version 1 files hold a single organization.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditExportV1 {
+    pub version: u32,
+    pub exported_on: String,
+    pub server: String,
+    pub org: String,
+    pub org_id: String,
+    pub items: Vec<AuditItem>,
+}
+
+impl From<AuditExportV1> for AuditExport {
+    fn from(export: AuditExportV1) -> Self {
+        AuditExport {
+            version: EXPORT_VERSION,
+            exported_on: export.exported_on,
+            server: export.server,
+            orgs: vec![OrgExport {
+                org: export.org,
+                org_id: export.org_id,
+                items: export.items,
+            }],
+        }
+    }
+}
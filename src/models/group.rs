@@ -1,6 +1,8 @@
 use serde::Deserialize;
 use serde::Serialize;
 
+use super::kind::{GroupType, LocationType};
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Group {
@@ -32,11 +34,11 @@ pub struct Result {
     pub links: Links2,
     pub display_name: String,
     pub id: String,
-    pub location_type: String,
+    pub location_type: LocationType,
     pub managed_by: String,
     pub name: String,
     #[serde(rename = "type")]
-    pub type_field: String,
+    pub type_field: GroupType,
     pub site: Option<String>,
 }
 
@@ -65,10 +67,3 @@ pub struct SelfField2 {
 pub struct ItemIds {
     pub item_ids: Vec<String>,
 }
-
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct ItemRemoveResponse {
-    pub message: String,
-    pub error_code: String,
-}
@@ -0,0 +1,175 @@
+use std::fmt;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+// Values VB365 adds in later versions deserialize into `Unknown` and are written back unchanged.
+// Matching is case-sensitive like the API, so "security" is `Unknown` rather than `Security`.
+
+/// The `type` of an audit item.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ItemKind {
+    #[default]
+    User,
+    Group,
+    Site,
+    Team,
+    #[serde(untagged)]
+    Unknown(String),
+}
+
+impl ItemKind {
+    pub fn as_str(&self) -> &str {
+        match self {
+            ItemKind::User => "User",
+            ItemKind::Group => "Group",
+            ItemKind::Site => "Site",
+            ItemKind::Team => "Team",
+            ItemKind::Unknown(value) => value,
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UserType {
+    #[default]
+    User,
+    Shared,
+    Public,
+    #[serde(untagged)]
+    Unknown(String),
+}
+
+impl UserType {
+    pub fn as_str(&self) -> &str {
+        match self {
+            UserType::User => "User",
+            UserType::Shared => "Shared",
+            UserType::Public => "Public",
+            UserType::Unknown(value) => value,
+        }
+    }
+
+    /// Whether VB365 accepts users of this type as audit items.
+    pub fn is_supported(&self) -> bool {
+        !matches!(self, UserType::Unknown(_))
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GroupType {
+    #[default]
+    Office365,
+    Security,
+    Distribution,
+    DynamicDistribution,
+    #[serde(untagged)]
+    Unknown(String),
+}
+
+impl GroupType {
+    pub fn as_str(&self) -> &str {
+        match self {
+            GroupType::Office365 => "Office365",
+            GroupType::Security => "Security",
+            GroupType::Distribution => "Distribution",
+            GroupType::DynamicDistribution => "DynamicDistribution",
+            GroupType::Unknown(value) => value,
+        }
+    }
+
+    /// Whether VB365 accepts groups of this type as audit items.
+    pub fn is_supported(&self) -> bool {
+        !matches!(self, GroupType::Unknown(_))
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LocationType {
+    #[default]
+    Cloud,
+    OnPremises,
+    Hybrid,
+    #[serde(untagged)]
+    Unknown(String),
+}
+
+impl LocationType {
+    pub fn as_str(&self) -> &str {
+        match self {
+            LocationType::Cloud => "Cloud",
+            LocationType::OnPremises => "OnPremises",
+            LocationType::Hybrid => "Hybrid",
+            LocationType::Unknown(value) => value,
+        }
+    }
+}
+
+impl fmt::Display for ItemKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Display for UserType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Display for GroupType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Display for LocationType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_values_deserialize_to_their_variant() {
+        let kinds: Vec<ItemKind> = serde_json::from_str(r#"["User","Group","Site","Team"]"#).unwrap();
+        assert_eq!(kinds, vec![ItemKind::User, ItemKind::Group, ItemKind::Site, ItemKind::Team]);
+        let group: GroupType = serde_json::from_str(r#""DynamicDistribution""#).unwrap();
+        assert_eq!(group, GroupType::DynamicDistribution);
+        let location: LocationType = serde_json::from_str(r#""OnPremises""#).unwrap();
+        assert_eq!(location, LocationType::OnPremises);
+    }
+
+    #[test]
+    fn unknown_values_round_trip() {
+        let kind: ItemKind = serde_json::from_str(r#""Weird""#).unwrap();
+        assert_eq!(kind, ItemKind::Unknown("Weird".to_string()));
+        assert_eq!(serde_json::to_string(&kind).unwrap(), r#""Weird""#);
+        assert_eq!(kind.to_string(), "Weird");
+
+        let user: UserType = serde_json::from_str(r#""Weird""#).unwrap();
+        assert_eq!(user, UserType::Unknown("Weird".to_string()));
+        assert_eq!(serde_json::to_string(&user).unwrap(), r#""Weird""#);
+
+        let location: LocationType = serde_json::from_str(r#""Weird""#).unwrap();
+        assert_eq!(serde_json::to_string(&location).unwrap(), r#""Weird""#);
+    }
+
+    #[test]
+    fn matching_is_case_sensitive() {
+        let group: GroupType = serde_json::from_str(r#""security""#).unwrap();
+        assert_eq!(group, GroupType::Unknown("security".to_string()));
+        assert_eq!(serde_json::to_string(&group).unwrap(), r#""security""#);
+    }
+
+    #[test]
+    fn unknown_types_are_not_supported() {
+        assert!(UserType::Shared.is_supported());
+        assert!(!UserType::Unknown("Weird".to_string()).is_supported());
+        assert!(GroupType::Security.is_supported());
+        assert!(!GroupType::Unknown("Weird".to_string()).is_supported());
+    }
+}
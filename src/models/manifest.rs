@@ -0,0 +1,17 @@
+use serde::Deserialize;
+use serde::Serialize;
+
+/// Desired audit scope, each entry is matched against the directory id, name (UPN) or display name.
+/// Sites are matched by id, url or title and teams by id, mail or display name.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub org: Option<String>,
+    #[serde(default)]
+    pub users: Vec<String>,
+    #[serde(default)]
+    pub groups: Vec<String>,
+    #[serde(default)]
+    pub sites: Vec<String>,
+    #[serde(default)]
+    pub teams: Vec<String>,
+}
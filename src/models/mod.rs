@@ -1,7 +1,14 @@
 pub mod audit;
 pub mod config;
+pub mod export;
 pub mod group;
+pub mod kind;
+pub mod manifest;
 pub mod notification;
 pub mod oauth;
 pub mod org;
+pub mod page;
+pub mod report;
+pub mod site;
+pub mod team;
 pub mod user;
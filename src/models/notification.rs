@@ -13,9 +13,24 @@ pub struct NotificationData {
     pub request_id: String,
 }
 
+/// The settings returned by GET AuditEmailSettings, values the server leaves out are `None`.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AuditEmailSettings {
+    pub enable_notification: bool,
+    pub authentication_type: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub subject: Option<String>,
+    pub user_id: Option<String>,
+    pub request_id: Option<String>,
+}
+
+/// The Microsoft 365 sign-in from the last setup, saved so the settings can be changed later
+/// without signing in again.
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct NotificationResponse {
-    pub message: String,
-    pub error_code: String,
+pub struct NotificationSignIn {
+    pub request_id: String,
+    pub user_id: String,
 }
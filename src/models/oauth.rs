@@ -34,3 +34,111 @@ pub struct CompleteResponse {
     pub request_id: String,
     pub user_id: String,
 }
+
+/// The parameters Microsoft sends back on the redirect after sign-in.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct OAuthCallback {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub session_state: Option<String>,
+    pub admin_consent: Option<bool>,
+    pub error: Option<String>,
+    pub error_description: Option<String>,
+}
+
+impl OAuthCallback {
+    /// Parses the query of a redirect, a full URL or just the query string, decoding the values.
+    /// Parameters it does not know are ignored.
+    pub fn parse(input: &str) -> OAuthCallback {
+        let query = input.split_once('?').map(|(_, query)| query).unwrap_or(input);
+        let query = query.split('#').next().unwrap_or_default();
+        let mut callback = OAuthCallback::default();
+        for (key, value) in url::form_urlencoded::parse(query.as_bytes()) {
+            let value = value.into_owned();
+            match key.as_ref() {
+                "code" => callback.code = Some(value),
+                "state" => callback.state = Some(value),
+                "session_state" => callback.session_state = Some(value),
+                "admin_consent" => callback.admin_consent = Some(value.eq_ignore_ascii_case("true")),
+                "error" => callback.error = Some(value),
+                "error_description" => callback.error_description = Some(value),
+                _ => {}
+            }
+        }
+        callback
+    }
+
+    /// True when this is an answer to the sign-in, either a code or an error.
+    pub fn is_callback(&self) -> bool {
+        self.code.is_some() || self.error.is_some()
+    }
+
+    /// The code and state to complete the sign-in with, or the error Microsoft returned.
+    pub fn into_complete_request(self) -> Result<CompleteRequest, anyhow::Error> {
+        if let Some(error) = self.error {
+            return Err(match self.error_description {
+                Some(description) => anyhow::anyhow!("Sign-in failed: {}: {}", error, description),
+                None => anyhow::anyhow!("Sign-in failed: {}", error),
+            });
+        }
+        match (self.code, self.state) {
+            (Some(code), Some(state)) => Ok(CompleteRequest { code, state }),
+            (None, _) => Err(anyhow::anyhow!("The redirect has no code")),
+            (_, None) => Err(anyhow::anyhow!("The redirect has no state")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_values() {
+        let callback = OAuthCallback::parse("code=a%2Bb+c&state=x%3Dy");
+        assert_eq!(callback.code.as_deref(), Some("a+b c"));
+        assert_eq!(callback.state.as_deref(), Some("x=y"));
+    }
+
+    #[test]
+    fn reads_parameters_by_name() {
+        let callback = OAuthCallback::parse(
+            "http://localhost/cb?session_state=s&admin_consent=True&state=st&extra=1&code=c",
+        );
+        assert_eq!(callback.code.as_deref(), Some("c"));
+        assert_eq!(callback.state.as_deref(), Some("st"));
+        assert_eq!(callback.session_state.as_deref(), Some("s"));
+        assert_eq!(callback.admin_consent, Some(true));
+        assert_eq!(
+            callback.into_complete_request().unwrap(),
+            CompleteRequest {
+                code: "c".to_string(),
+                state: "st".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn ignores_the_fragment() {
+        let callback = OAuthCallback::parse("/cb?code=c&state=st#section");
+        assert_eq!(callback.state.as_deref(), Some("st"));
+    }
+
+    #[test]
+    fn error_callback_fails_with_the_description() {
+        let callback = OAuthCallback::parse(
+            "error=access_denied&error_description=AADSTS65004%3A+User+declined+to+consent&state=st",
+        );
+        assert!(callback.is_callback());
+        let error = callback.into_complete_request().unwrap_err().to_string();
+        assert_eq!(error, "Sign-in failed: access_denied: AADSTS65004: User declined to consent");
+    }
+
+    #[test]
+    fn missing_code_or_state_fails() {
+        let no_code = OAuthCallback::parse("state=st");
+        assert!(!no_code.is_callback());
+        assert!(no_code.into_complete_request().is_err());
+        assert!(OAuthCallback::parse("code=c").into_complete_request().is_err());
+    }
+}
@@ -0,0 +1,12 @@
+use serde::Deserialize;
+use serde::Serialize;
+
+/// One page of a paged VB365 collection such as Organizations/{id}/Users.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Page<T> {
+    pub offset: i64,
+    pub limit: i64,
+    pub set_id: Option<String>,
+    pub results: Vec<T>,
+}
@@ -0,0 +1,41 @@
+use serde::Serialize;
+
+use super::kind::{ItemKind, LocationType};
+
+/// Coverage of one user or group type in one location.
+#[derive(Default, Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CoverageRow {
+    pub kind: ItemKind,
+    #[serde(rename = "type")]
+    pub type_field: String,
+    pub location_type: LocationType,
+    pub total: usize,
+    pub audited: usize,
+    pub coverage: f64,
+}
+
+/// A user or group that is not audited.
+#[derive(Default, Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UncoveredItem {
+    pub org: String,
+    pub kind: ItemKind,
+    pub display_name: String,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub type_field: String,
+    pub location_type: LocationType,
+    pub id: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CoverageReport {
+    pub org: String,
+    pub coverage: Vec<CoverageRow>,
+    pub uncovered: Vec<UncoveredItem>,
+    pub must_audit_missing: Vec<String>,
+    /// Users and groups of types that cannot be audited, these are not counted.
+    pub unsupported: usize,
+}
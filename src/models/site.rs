@@ -0,0 +1,88 @@
+use serde::Deserialize;
+use serde::Serialize;
+
+use super::kind::LocationType;
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Site {
+    pub offset: i64,
+    pub limit: i64,
+    #[serde(rename = "_links", default)]
+    pub links: Links,
+    pub results: Vec<Result>,
+    pub set_id: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Links {
+    #[serde(rename = "self")]
+    pub self_field: SelfField,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SelfField {
+    pub href: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Result {
+    pub id: String,
+    pub url: String,
+    #[serde(default)]
+    pub parent_url: Option<String>,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub is_cloud: bool,
+    #[serde(default)]
+    pub is_personal: bool,
+    #[serde(default)]
+    pub is_available: bool,
+    #[serde(rename = "_links", default)]
+    pub links: Links2,
+}
+
+impl Result {
+    /// Sites without a title are shown by their url.
+    pub fn display_name(&self) -> &str {
+        if self.title.is_empty() {
+            &self.url
+        } else {
+            &self.title
+        }
+    }
+
+    pub fn location_type(&self) -> LocationType {
+        if self.is_cloud {
+            LocationType::Cloud
+        } else {
+            LocationType::OnPremises
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Links2 {
+    #[serde(rename = "self")]
+    pub self_field: SelfField2,
+    pub organization: Organization,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SelfField2 {
+    pub href: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Organization {
+    pub href: String,
+}
@@ -1,6 +1,8 @@
 use serde::Deserialize;
 use serde::Serialize;
 
+use super::kind::{LocationType, UserType};
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct User {
@@ -32,8 +34,8 @@ pub struct Result {
     pub display_name: String,
     pub name: String,
     #[serde(rename = "type")]
-    pub type_field: String,
-    pub location_type: String,
+    pub type_field: UserType,
+    pub location_type: LocationType,
     #[serde(rename = "_links")]
     pub links: Links2,
 }
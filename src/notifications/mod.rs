@@ -1,14 +1,20 @@
-use reqwest::header::{HeaderMap, CONTENT_LENGTH};
+use comfy_table::modifiers::UTF8_ROUND_CORNERS;
+use comfy_table::presets::UTF8_FULL;
+use comfy_table::{modifiers::UTF8_SOLID_INNER_BORDERS, Table};
+use reqwest::{header::{HeaderMap, CONTENT_LENGTH}, Client};
 use vauth::{Profile, VProfile, build_url};
 use anyhow::Result;
 
-use crate::{models::{config::Config, notification::NotificationData}, login, setup::set_up_auth};
+use std::path::PathBuf;
 
-pub async fn setup_notifications(username: &String, address: &String, config: Config) -> Result<()> {
+use crate::{models::{config::{Config, Vb356}, notification::{AuditEmailSettings, NotificationData, NotificationSignIn}}, cli::{SetupArgs, UpdateArgs}, login::login, setup::{set_up_auth, SIGN_IN_REQUEST_TIMEOUT}, token::{cache_path, read_optional, write_private}};
+
+pub async fn setup_notifications(config: Config, args: &SetupArgs) -> Result<()> {
+    let address = &config.vb365.address;
     let mut profile = Profile::get_profile(VProfile::VB365);
-    let client = login(username, address, &mut profile).await?;
+    let client = login(&config.vb365, &mut profile).await?;
     println!("Logged in successfully!");
-    let complete_response = set_up_auth(&config, address, &profile, &client).await?;
+    let complete_response = set_up_auth(&config, address, &profile, &client, args.headless).await?;
 
     let nd = NotificationData {
         enable_notification: true,
@@ -22,10 +28,22 @@ pub async fn setup_notifications(username: &String, address: &String, config: Co
 
     let url = build_url(address, &"AuditEmailSettings".to_string(), &profile)?;
 
-    let response = client.put(&url).json(&nd).send().await?;
+    let response = client
+        .put(&url)
+        .json(&nd)
+        .timeout(SIGN_IN_REQUEST_TIMEOUT)
+        .send()
+        .await?;
 
     if response.status().is_success() {
         println!("Notification settings updated successfully!");
+        let sign_in = NotificationSignIn {
+            request_id: nd.request_id,
+            user_id: nd.user_id,
+        };
+        if let Err(e) = save_sign_in(&config.vb365, &sign_in) {
+            eprintln!("Unable to save the sign-in, later changes will need setup again: {}", e);
+        }
     } else {
         println!("Notification settings update failed!");
     }
@@ -33,10 +51,181 @@ pub async fn setup_notifications(username: &String, address: &String, config: Co
     Ok(())
 }
 
-pub async fn sent_test_email(username: &String, address: &String) -> Result<()> {
+/// Sign-in file for the server and user, next to the token cache.
+fn sign_in_path(vb365: &Vb356) -> Result<PathBuf> {
+    cache_path(vb365, "notifications", "json")
+}
+
+fn save_sign_in(vb365: &Vb356, sign_in: &NotificationSignIn) -> Result<()> {
+    write_private(&sign_in_path(vb365)?, serde_json::to_string_pretty(sign_in)?.as_bytes())
+}
+
+fn load_sign_in(vb365: &Vb356) -> Result<Option<NotificationSignIn>> {
+    match read_optional(&sign_in_path(vb365)?)? {
+        Some(contents) => Ok(Some(serde_json::from_str(&contents)?)),
+        None => Ok(None),
+    }
+}
+
+async fn current_settings(address: &String, profile: &Profile, client: &Client) -> Result<AuditEmailSettings> {
+    let url = build_url(address, &"AuditEmailSettings".to_string(), profile)?;
+    let response = client.get(&url).send().await?;
+    if !response.status().is_success() {
+        let response_text = response.text().await?;
+        return Err(anyhow::anyhow!("Failed to get the notification settings: {}", response_text));
+    }
+    Ok(response.json().await?)
+}
+
+/// Puts the current settings back with `change` applied, reusing the Microsoft 365 sign-in
+/// from the last setup instead of running the browser flow again.
+async fn change_notifications(config: &Config, change: impl FnOnce(&mut NotificationData)) -> Result<()> {
+    let address = &config.vb365.address;
+    let mut profile = Profile::get_profile(VProfile::VB365);
+    let client = login(&config.vb365, &mut profile).await?;
+    let current = current_settings(address, &profile, &client).await?;
+
+    let sign_in = match current.request_id.clone().filter(|x| !x.is_empty()) {
+        Some(request_id) => NotificationSignIn {
+            request_id,
+            user_id: current.user_id.clone().unwrap_or(config.notification.user_id.clone()),
+        },
+        None => load_sign_in(&config.vb365)?.ok_or_else(|| {
+            anyhow::anyhow!("No saved sign-in for {}, run notifications setup first", address)
+        })?,
+    };
+
+    let mut nd = NotificationData {
+        enable_notification: current.enable_notification,
+        authentication_type: current.authentication_type.unwrap_or("Microsoft365".to_string()),
+        to: current.to.unwrap_or(config.notification.to.clone()),
+        from: current.from.unwrap_or(config.notification.from.clone()),
+        subject: current.subject.unwrap_or(config.notification.subject.clone()),
+        user_id: sign_in.user_id,
+        request_id: sign_in.request_id,
+    };
+    change(&mut nd);
+
+    let url = build_url(address, &"AuditEmailSettings".to_string(), &profile)?;
+    let response = client.put(&url).json(&nd).send().await?;
+    if !response.status().is_success() {
+        let response_text = response.text().await?;
+        return Err(anyhow::anyhow!(
+            "Notification settings update failed, the saved sign-in may have expired, run notifications setup again: {}",
+            response_text
+        ));
+    }
+    Ok(())
+}
+
+pub async fn set_notifications_enabled(config: &Config, enabled: bool) -> Result<()> {
+    change_notifications(config, |nd| nd.enable_notification = enabled).await?;
+    if enabled {
+        println!("Notifications enabled successfully!");
+    } else {
+        println!("Notifications disabled successfully!");
+    }
+    Ok(())
+}
+
+pub async fn update_notifications(config: &Config, args: &UpdateArgs) -> Result<()> {
+    if args.to.is_none() && args.from.is_none() && args.subject.is_none() {
+        return Err(anyhow::anyhow!("Nothing to update, give --to, --from or --subject"));
+    }
+    change_notifications(config, |nd| {
+        if let Some(to) = &args.to {
+            nd.to = to.clone();
+        }
+        if let Some(from) = &args.from {
+            nd.from = from.clone();
+        }
+        if let Some(subject) = &args.subject {
+            nd.subject = subject.clone();
+        }
+    })
+    .await?;
+    println!("Notification settings updated successfully!");
+    Ok(())
+}
+
+pub async fn show_notifications(config: &Config) -> Result<()> {
+    let address = &config.vb365.address;
+    let mut profile = Profile::get_profile(VProfile::VB365);
+    let client = login(&config.vb365, &mut profile).await?;
+
+    let current = current_settings(address, &profile, &client).await?;
+
+    let wanted = &config.notification;
+    let same_address = |a: &str, b: &str| a.trim().eq_ignore_ascii_case(b.trim());
+    // Setup always enables notifications with Microsoft 365 authentication.
+    let rows = [
+        (
+            "Enabled",
+            current.enable_notification.to_string(),
+            "true".to_string(),
+            current.enable_notification,
+        ),
+        (
+            "Authentication",
+            current.authentication_type.clone().unwrap_or_default(),
+            "Microsoft365".to_string(),
+            current.authentication_type.as_deref() == Some("Microsoft365"),
+        ),
+        (
+            "From",
+            current.from.clone().unwrap_or_default(),
+            wanted.from.clone(),
+            same_address(current.from.as_deref().unwrap_or_default(), &wanted.from),
+        ),
+        (
+            "To",
+            current.to.clone().unwrap_or_default(),
+            wanted.to.clone(),
+            same_address(current.to.as_deref().unwrap_or_default(), &wanted.to),
+        ),
+        (
+            "Subject",
+            current.subject.clone().unwrap_or_default(),
+            wanted.subject.clone(),
+            current.subject.as_deref().unwrap_or_default() == wanted.subject,
+        ),
+        (
+            "User ID",
+            current.user_id.clone().unwrap_or_default(),
+            wanted.user_id.clone(),
+            current.user_id.as_deref().unwrap_or_default() == wanted.user_id,
+        ),
+    ];
+
+    let mut table = Table::new();
+    table
+        .load_preset(UTF8_FULL)
+        .apply_modifier(UTF8_ROUND_CORNERS)
+        .apply_modifier(UTF8_SOLID_INNER_BORDERS)
+        .set_header(vec!["Setting", "VB365", "config.toml", ""]);
+    for (name, current, wanted, matches) in rows.iter() {
+        let marker = if *matches { "" } else { "differs" };
+        table.add_row(vec![name.to_string(), current.clone(), wanted.clone(), marker.to_string()]);
+    }
+    println!("{table}");
+
+    let drift = rows.iter().filter(|x| !x.3).count();
+    if drift == 0 {
+        println!("The notification settings match config.toml");
+    } else {
+        println!(
+            "{} settings differ from config.toml, run notifications setup to apply them",
+            drift
+        );
+    }
+    Ok(())
+}
+
+pub async fn sent_test_email(vb365: &Vb356) -> Result<()> {
+    let address = &vb365.address;
     let mut profile = Profile::get_profile(VProfile::VB365);
 
-    let client = login(username, address, &mut profile).await?;
+    let client = login(vb365, &mut profile).await?;
 
     let mut headers = HeaderMap::new();
     headers.insert(CONTENT_LENGTH, "0".parse().unwrap());
@@ -0,0 +1,30 @@
+use std::io;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::cli::OutputFormat;
+
+/// Writes the rows to stdout in a machine-readable format.
+///
+/// Table output differs per command so it is left to the caller. CSV rows must be flat.
+pub fn print_serialized<T: Serialize>(rows: &[T], format: OutputFormat) -> Result<()> {
+    match format {
+        OutputFormat::Table => return Err(anyhow::anyhow!("Table output is not serialized")),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(rows)?),
+        OutputFormat::JsonLines => {
+            for row in rows {
+                println!("{}", serde_json::to_string(row)?);
+            }
+        }
+        OutputFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(io::stdout());
+            for row in rows {
+                writer.serialize(row)?;
+            }
+            writer.flush()?;
+        }
+        OutputFormat::Yaml => print!("{}", serde_yaml::to_string(rows)?),
+    }
+    Ok(())
+}
@@ -0,0 +1,341 @@
+use std::{borrow::Cow, fs, path::Path};
+
+use anyhow::Result;
+use reqwest::Client;
+use vauth::{Profile, VProfile, build_url};
+
+use crate::{
+    audit::{audit_items, confirm_action, org_groups, org_sites, org_teams, org_users, post_audit_items},
+    cli::{DirectoryFilter, ManifestArgs},
+    login::{get_org_id, login},
+    models::{audit::AuditItem, config::Vb356, group::ItemIds, manifest::Manifest},
+};
+
+/// The audit item changes needed to match a manifest.
+#[derive(Default, Debug)]
+pub struct Plan {
+    pub add: Vec<AuditItem>,
+    pub remove: Vec<AuditItem>,
+    pub unresolved: Vec<String>,
+}
+
+impl Plan {
+    pub fn is_empty(&self) -> bool {
+        self.add.is_empty() && self.remove.is_empty()
+    }
+}
+
+pub fn read_manifest(path: &str) -> Result<Manifest> {
+    let file_string = fs::read_to_string(path)?;
+    let extension = Path::new(path).extension().and_then(|x| x.to_str());
+    let manifest = match extension {
+        Some("yaml") | Some("yml") => serde_yaml::from_str(&file_string)?,
+        _ => toml::from_str(&file_string)?,
+    };
+    Ok(manifest)
+}
+
+/// Finds the entry by directory id, then name (UPN), then display name.
+pub fn resolve<'a, T>(
+    entry: &str,
+    items: &'a [T],
+    fields: impl Fn(&T) -> (&str, &str, &str),
+) -> Result<&'a T, String> {
+    resolve_any(entry, items, "the directory", |x| {
+        let (id, name, display_name) = fields(x);
+        ([id.into()], name.into(), display_name.into())
+    })
+}
+
+/// Finds the entry by any of the ids, then name (UPN) case-insensitively, then display name.
+/// `place` names where the items came from when nothing matches.
+pub fn resolve_any<'a, T, const N: usize>(
+    entry: &str,
+    items: &'a [T],
+    place: &str,
+    fields: impl Fn(&T) -> ([Cow<'_, str>; N], Cow<'_, str>, Cow<'_, str>),
+) -> Result<&'a T, String> {
+    let has_id = |x: &T| fields(x).0.iter().any(|id| !id.is_empty() && id == entry);
+    if let Some(item) = items.iter().find(|x| has_id(x)) {
+        return Ok(item);
+    }
+    if let Some(item) = items.iter().find(|x| fields(x).1.eq_ignore_ascii_case(entry)) {
+        return Ok(item);
+    }
+    let matches: Vec<&T> = items.iter().filter(|x| fields(x).2 == entry).collect();
+    match matches.len() {
+        0 => Err(format!("{} not found in {}", entry, place)),
+        1 => Ok(matches[0]),
+        n => Err(format!("{} matches {} display names, use the UPN or id", entry, n)),
+    }
+}
+
+pub async fn build_plan(
+    manifest: &Manifest,
+    org_id: &String,
+    address: &String,
+    profile: &Profile,
+    client: &Client,
+) -> Result<Plan> {
+    let current = audit_items(org_id, address, profile, client).await?;
+    let mut plan = Plan::default();
+    let mut wanted_ids: Vec<String> = Vec::new();
+
+    if !manifest.users.is_empty() {
+        let users = org_users(org_id, address, profile, client, &DirectoryFilter::default()).await?.results;
+        for entry in manifest.users.iter() {
+            match resolve(entry, &users, |x| (&x.id, &x.name, &x.display_name)) {
+                Ok(user) if !user.type_field.is_supported() => plan
+                    .unresolved
+                    .push(format!("user {} has unsupported type {}", entry, user.type_field)),
+                Ok(user) => {
+                    wanted_ids.push(user.id.clone());
+                    if !current.iter().any(|x| x.user.as_ref().is_some_and(|u| u.id == user.id)) {
+                        plan.add.push(AuditItem::from(user.clone()));
+                    }
+                }
+                Err(reason) => plan.unresolved.push(format!("user {}", reason)),
+            }
+        }
+    }
+
+    if !manifest.groups.is_empty() {
+        let groups = org_groups(org_id, address, profile, client, &DirectoryFilter::default()).await?.results;
+        for entry in manifest.groups.iter() {
+            match resolve(entry, &groups, |x| (&x.id, &x.name, &x.display_name)) {
+                Ok(group) if !group.type_field.is_supported() => plan
+                    .unresolved
+                    .push(format!("group {} has unsupported type {}", entry, group.type_field)),
+                Ok(group) => {
+                    wanted_ids.push(group.id.clone());
+                    if !current.iter().any(|x| x.group.as_ref().is_some_and(|g| g.id == group.id)) {
+                        plan.add.push(AuditItem::from(group.clone()));
+                    }
+                }
+                Err(reason) => plan.unresolved.push(format!("group {}", reason)),
+            }
+        }
+    }
+
+    if !manifest.sites.is_empty() {
+        let sites = org_sites(org_id, address, profile, client, &DirectoryFilter::default()).await?.results;
+        for entry in manifest.sites.iter() {
+            match resolve(entry, &sites, |x| (&x.id, &x.url, &x.title)) {
+                Ok(site) => {
+                    wanted_ids.push(site.id.clone());
+                    if !current.iter().any(|x| x.site.as_ref().is_some_and(|s| s.id == site.id)) {
+                        plan.add.push(AuditItem::from(site.clone()));
+                    }
+                }
+                Err(reason) => plan.unresolved.push(format!("site {}", reason)),
+            }
+        }
+    }
+
+    if !manifest.teams.is_empty() {
+        let teams = org_teams(org_id, address, profile, client, &DirectoryFilter::default()).await?.results;
+        for entry in manifest.teams.iter() {
+            match resolve(entry, &teams, |x| (&x.id, &x.mail, &x.display_name)) {
+                Ok(team) => {
+                    wanted_ids.push(team.id.clone());
+                    if !current.iter().any(|x| x.team.as_ref().is_some_and(|t| t.id == team.id)) {
+                        plan.add.push(AuditItem::from(team.clone()));
+                    }
+                }
+                Err(reason) => plan.unresolved.push(format!("team {}", reason)),
+            }
+        }
+    }
+
+    plan.remove = removals(current, &wanted_ids);
+    Ok(plan)
+}
+
+/// Current items the manifest does not list, including every item of a kind it has no section for.
+fn removals(current: Vec<AuditItem>, wanted_ids: &[String]) -> Vec<AuditItem> {
+    current
+        .into_iter()
+        .filter(|item| {
+            let (_, id) = item.display_name_and_id();
+            !id.is_empty() && !wanted_ids.contains(&id)
+        })
+        .collect()
+}
+
+fn print_plan(plan: &Plan) {
+    for reason in plan.unresolved.iter() {
+        println!("  ! {}", reason);
+    }
+    for item in plan.add.iter() {
+        let (name, _) = item.display_name_and_id();
+        println!("  + {:<5} {}", kind(item), name);
+    }
+    for item in plan.remove.iter() {
+        let (name, _) = item.display_name_and_id();
+        println!("  - {:<5} {}", kind(item), name);
+    }
+    println!(
+        "Plan: {} to add, {} to remove, {} unresolved.",
+        plan.add.len(),
+        plan.remove.len(),
+        plan.unresolved.len()
+    );
+}
+
+fn kind(item: &AuditItem) -> &'static str {
+    if item.user.is_some() {
+        "user"
+    } else if item.group.is_some() {
+        "group"
+    } else if item.site.is_some() {
+        "site"
+    } else {
+        "team"
+    }
+}
+
+async fn manifest_plan(
+    args: &ManifestArgs,
+    vb365: &Vb356,
+) -> Result<(Plan, String, Profile, Client)> {
+    let address = &vb365.address;
+    let manifest = read_manifest(&args.manifest)?;
+
+    let mut profile = Profile::get_profile(VProfile::VB365);
+    let client = login(vb365, &mut profile).await?;
+    let org = args.org.as_deref().or(manifest.org.as_deref());
+    let org_id = get_org_id(address, &profile, &client, org).await?;
+
+    let plan = build_plan(&manifest, &org_id, address, &profile, &client).await?;
+    Ok((plan, org_id, profile, client))
+}
+
+pub async fn plan_audit_items(vb365: &Vb356, args: &ManifestArgs) -> Result<()> {
+    let (plan, _, _, _) = manifest_plan(args, vb365).await?;
+    if plan.is_empty() && plan.unresolved.is_empty() {
+        println!("No changes, the audit items match {}", args.manifest);
+        return Ok(());
+    }
+    print_plan(&plan);
+    if !plan.unresolved.is_empty() {
+        return Err(anyhow::anyhow!(
+            "{} manifest entries could not be resolved",
+            plan.unresolved.len()
+        ));
+    }
+    Ok(())
+}
+
+pub async fn apply_audit_items(vb365: &Vb356, args: &ManifestArgs) -> Result<()> {
+    let address = &vb365.address;
+    let (plan, org_id, profile, client) = manifest_plan(args, vb365).await?;
+    if !plan.unresolved.is_empty() {
+        print_plan(&plan);
+        return Err(anyhow::anyhow!(
+            "{} manifest entries could not be resolved, nothing was applied",
+            plan.unresolved.len()
+        ));
+    }
+    if plan.is_empty() {
+        println!("No changes, the audit items match {}", args.manifest);
+        return Ok(());
+    }
+    print_plan(&plan);
+    confirm_action(args.yes);
+
+    let mut rejected = 0;
+    if !plan.add.is_empty() {
+        let rejected_items = post_audit_items(&plan.add, &org_id, address, &profile, &client).await?;
+        for (item, reason) in rejected_items.iter() {
+            println!("  ! {} was rejected: {}", item.display_name_and_id().0, reason);
+        }
+        rejected = rejected_items.len();
+        println!("{} items added successfully!", plan.add.len() - rejected);
+    }
+
+    if !plan.remove.is_empty() {
+        let url = build_url(
+            address,
+            &format!("Organizations/{}/AuditItems/remove", org_id),
+            &profile,
+        )?;
+        let item_ids = ItemIds {
+            item_ids: plan.remove.iter().filter_map(|x| x.id.clone()).collect(),
+        };
+        let response = client.post(url).json(&item_ids).send().await?;
+        if !response.status().is_success() {
+            let response_text = response.text().await?;
+            return Err(anyhow::anyhow!("Removing audit items failed! {}", response_text));
+        }
+        println!("{} items removed successfully!", plan.remove.len());
+    }
+
+    if rejected > 0 {
+        return Err(anyhow::anyhow!("{} audit items could not be added", rejected));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{site, user};
+
+    // (id, name, display name)
+    const ITEMS: [(&str, &str, &str); 4] = [
+        ("1", "adele@contoso.com", "Adele"),
+        ("2", "1", "Alex"),
+        ("3", "alex@contoso.com", "Adele"),
+        ("4", "megan@contoso.com", "alex@CONTOSO.com"),
+    ];
+
+    fn resolve_id(entry: &str) -> Result<&'static str, String> {
+        resolve(entry, &ITEMS, |x| (x.0, x.1, x.2)).map(|x| x.0)
+    }
+
+    #[test]
+    fn resolves_id_before_name() {
+        assert_eq!(resolve_id("1"), Ok("1"));
+    }
+
+    #[test]
+    fn resolves_name_case_insensitively_before_display_name() {
+        assert_eq!(resolve_id("ALEX@contoso.com"), Ok("3"));
+        assert_eq!(resolve_id("alex@CONTOSO.com"), Ok("3"));
+    }
+
+    #[test]
+    fn resolves_a_unique_display_name() {
+        assert_eq!(resolve_id("Alex"), Ok("2"));
+    }
+
+    #[test]
+    fn ambiguous_display_name_is_an_error() {
+        assert_eq!(resolve_id("Adele"), Err("Adele matches 2 display names, use the UPN or id".to_string()));
+    }
+
+    #[test]
+    fn unknown_entry_is_an_error() {
+        assert_eq!(resolve_id("Nobody"), Err("Nobody not found in the directory".to_string()));
+    }
+
+    #[test]
+    fn removes_items_the_manifest_does_not_list() {
+        let wanted = AuditItem::from(user::Result {
+            id: "u1".to_string(),
+            ..Default::default()
+        });
+        let unwanted = AuditItem::from(user::Result {
+            id: "u2".to_string(),
+            ..Default::default()
+        });
+        // The manifest has no sites section, so the audited site goes too.
+        let site = AuditItem::from(site::Result {
+            id: "s1".to_string(),
+            ..Default::default()
+        });
+
+        let removed = removals(vec![wanted, unwanted.clone(), site.clone()], &["u1".to_string()]);
+        assert_eq!(removed, vec![unwanted, site]);
+    }
+}
@@ -0,0 +1,28 @@
+use comfy_table::modifiers::{UTF8_ROUND_CORNERS, UTF8_SOLID_INNER_BORDERS};
+use comfy_table::presets::UTF8_FULL;
+use comfy_table::Table;
+
+use crate::models::config::Config;
+
+pub fn list_profiles(config: &Config) {
+    if config.profiles.is_empty() && config.vb365.address.is_empty() {
+        println!("No profiles found in config.toml");
+        return;
+    }
+
+    let mut table = Table::new();
+
+    table
+        .load_preset(UTF8_FULL)
+        .apply_modifier(UTF8_ROUND_CORNERS)
+        .apply_modifier(UTF8_SOLID_INNER_BORDERS)
+        .set_header(vec!["Profile", "Address", "Username"]);
+
+    if !config.vb365.address.is_empty() {
+        table.add_row(vec!["(default)", &config.vb365.address, &config.vb365.username]);
+    }
+    for (name, profile) in config.profiles.iter() {
+        table.add_row(vec![name, &profile.vb365.address, &profile.vb365.username]);
+    }
+    print!("{table}");
+}
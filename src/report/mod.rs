@@ -0,0 +1,306 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use comfy_table::modifiers::UTF8_ROUND_CORNERS;
+use comfy_table::presets::UTF8_FULL;
+use comfy_table::{modifiers::UTF8_SOLID_INNER_BORDERS, Table};
+use reqwest::Client;
+use vauth::{Profile, VProfile};
+
+use crate::{
+    audit::{audit_items, org_groups, org_sites, org_teams, org_users},
+    cli::{DirectoryFilter, OutputFormat, ReportArgs},
+    login::{login, select_orgs},
+    models::{
+        config::Vb356,
+        kind::ItemKind,
+        manifest::Manifest,
+        org::OrgItem,
+        report::{CoverageReport, CoverageRow, UncoveredItem},
+    },
+    output::print_serialized,
+    plan::{read_manifest, resolve},
+};
+
+/// Adds a line to `missing` for each manifest entry that is not in the directory or not audited.
+fn check_must_audit<T>(
+    label: &str,
+    entries: &[String],
+    items: &[T],
+    fields: impl Fn(&T) -> (&str, &str, &str),
+    audited_ids: &[String],
+    missing: &mut Vec<String>,
+) {
+    for entry in entries {
+        match resolve(entry, items, &fields) {
+            Ok(item) if audited_ids.iter().any(|x| x == fields(item).0) => {}
+            Ok(_) => missing.push(format!("{} {} is not audited", label, entry)),
+            Err(reason) => missing.push(format!("{} {}", label, reason)),
+        }
+    }
+}
+
+fn percentage(audited: usize, total: usize) -> f64 {
+    if total == 0 {
+        return 100.0;
+    }
+    (audited as f64 * 1000.0 / total as f64).round() / 10.0
+}
+
+pub async fn coverage_report(vb365: &Vb356, args: &ReportArgs) -> Result<()> {
+    let address = &vb365.address;
+    let mut profile = Profile::get_profile(VProfile::VB365);
+
+    let client = login(vb365, &mut profile).await?;
+    let orgs = select_orgs(
+        address,
+        &profile,
+        &client,
+        args.org.as_deref(),
+        args.all_orgs,
+    )
+    .await?;
+    let must_audit = match &args.must_audit {
+        Some(path) => Some(read_manifest(path)?),
+        None => None,
+    };
+
+    let mut reports: Vec<CoverageReport> = Vec::new();
+    for org in orgs.iter() {
+        reports.push(build_report(org, must_audit.as_ref(), address, &profile, &client).await?);
+    }
+
+    match args.format {
+        OutputFormat::Table => reports.iter().for_each(print_report_table),
+        OutputFormat::Json if args.all_orgs => {
+            println!("{}", serde_json::to_string_pretty(&reports)?)
+        }
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&reports[0])?),
+        OutputFormat::Yaml if args.all_orgs => print!("{}", serde_yaml::to_string(&reports)?),
+        OutputFormat::Yaml => print!("{}", serde_yaml::to_string(&reports[0])?),
+        format => {
+            let uncovered: Vec<&UncoveredItem> =
+                reports.iter().flat_map(|x| x.uncovered.iter()).collect();
+            print_serialized(&uncovered, format)?
+        }
+    }
+
+    // On stderr so they are seen whatever the format, without mixing into csv or jsonl output.
+    for report in reports.iter() {
+        for reason in report.must_audit_missing.iter() {
+            eprintln!("  ! {}", reason);
+        }
+    }
+    let missing: usize = reports.iter().map(|x| x.must_audit_missing.len()).sum();
+    if missing > 0 {
+        return Err(anyhow::anyhow!(
+            "{} must-audit entries are not audited",
+            missing
+        ));
+    }
+    Ok(())
+}
+
+async fn build_report(
+    org: &OrgItem,
+    must_audit: Option<&Manifest>,
+    address: &String,
+    profile: &Profile,
+    client: &Client,
+) -> Result<CoverageReport> {
+    let audited_ids: Vec<String> = audit_items(&org.id, address, profile, client)
+        .await?
+        .iter()
+        .map(|x| x.display_name_and_id().1)
+        .collect();
+    let filter = DirectoryFilter::default();
+    let users = org_users(&org.id, address, profile, client, &filter)
+        .await?
+        .results;
+    let groups = org_groups(&org.id, address, profile, client, &filter)
+        .await?
+        .results;
+
+    // Users and groups of unsupported types cannot be audited so they are not counted.
+    let mut unsupported = 0;
+    let mut entries: Vec<(UncoveredItem, bool)> = Vec::new();
+    for user in users.iter() {
+        if !user.type_field.is_supported() {
+            unsupported += 1;
+            continue;
+        }
+        let entry = UncoveredItem {
+            org: org.name.clone(),
+            kind: ItemKind::User,
+            display_name: user.display_name.clone(),
+            name: user.name.clone(),
+            type_field: user.type_field.to_string(),
+            location_type: user.location_type.clone(),
+            id: user.id.clone(),
+        };
+        entries.push((entry, audited_ids.contains(&user.id)));
+    }
+    for group in groups.iter() {
+        if !group.type_field.is_supported() {
+            unsupported += 1;
+            continue;
+        }
+        let entry = UncoveredItem {
+            org: org.name.clone(),
+            kind: ItemKind::Group,
+            display_name: group.display_name.clone(),
+            name: group.name.clone(),
+            type_field: group.type_field.to_string(),
+            location_type: group.location_type.clone(),
+            id: group.id.clone(),
+        };
+        entries.push((entry, audited_ids.contains(&group.id)));
+    }
+
+    let mut rows: BTreeMap<(String, String, String), CoverageRow> = BTreeMap::new();
+    for (entry, audited) in entries.iter() {
+        let key = (
+            entry.kind.to_string(),
+            entry.type_field.clone(),
+            entry.location_type.to_string(),
+        );
+        let row = rows.entry(key).or_insert_with(|| CoverageRow {
+            kind: entry.kind.clone(),
+            type_field: entry.type_field.clone(),
+            location_type: entry.location_type.clone(),
+            ..Default::default()
+        });
+        row.total += 1;
+        if *audited {
+            row.audited += 1;
+        }
+    }
+    let coverage: Vec<CoverageRow> = rows
+        .into_values()
+        .map(|mut row| {
+            row.coverage = percentage(row.audited, row.total);
+            row
+        })
+        .collect();
+
+    let mut must_audit_missing: Vec<String> = Vec::new();
+    if let Some(manifest) = must_audit {
+        check_must_audit(
+            "user",
+            &manifest.users,
+            &users,
+            |x| (&x.id, &x.name, &x.display_name),
+            &audited_ids,
+            &mut must_audit_missing,
+        );
+        check_must_audit(
+            "group",
+            &manifest.groups,
+            &groups,
+            |x| (&x.id, &x.name, &x.display_name),
+            &audited_ids,
+            &mut must_audit_missing,
+        );
+        if !manifest.sites.is_empty() {
+            let sites = org_sites(&org.id, address, profile, client, &filter)
+                .await?
+                .results;
+            check_must_audit(
+                "site",
+                &manifest.sites,
+                &sites,
+                |x| (&x.id, &x.url, &x.title),
+                &audited_ids,
+                &mut must_audit_missing,
+            );
+        }
+        if !manifest.teams.is_empty() {
+            let teams = org_teams(&org.id, address, profile, client, &filter)
+                .await?
+                .results;
+            check_must_audit(
+                "team",
+                &manifest.teams,
+                &teams,
+                |x| (&x.id, &x.mail, &x.display_name),
+                &audited_ids,
+                &mut must_audit_missing,
+            );
+        }
+    }
+
+    Ok(CoverageReport {
+        org: org.name.clone(),
+        coverage,
+        uncovered: entries
+            .into_iter()
+            .filter(|(_, audited)| !audited)
+            .map(|(entry, _)| entry)
+            .collect(),
+        must_audit_missing,
+        unsupported,
+    })
+}
+
+fn print_report_table(report: &CoverageReport) {
+    let mut table = Table::new();
+    table
+        .load_preset(UTF8_FULL)
+        .apply_modifier(UTF8_ROUND_CORNERS)
+        .apply_modifier(UTF8_SOLID_INNER_BORDERS)
+        .set_header(vec![
+            "Kind", "Type", "Location", "Total", "Audited", "Coverage",
+        ]);
+    for row in report.coverage.iter() {
+        table.add_row(vec![
+            row.kind.to_string(),
+            row.type_field.clone(),
+            row.location_type.to_string(),
+            row.total.to_string(),
+            row.audited.to_string(),
+            format!("{:.1}%", row.coverage),
+        ]);
+    }
+    println!("Audit coverage for {}", report.org);
+    println!("{table}");
+
+    for kind in [ItemKind::User, ItemKind::Group] {
+        let rows = report.coverage.iter().filter(|x| x.kind == kind);
+        let (audited, total) = rows.fold((0, 0), |acc, x| (acc.0 + x.audited, acc.1 + x.total));
+        println!(
+            "{}s: {} of {} audited ({:.1}%)",
+            kind,
+            audited,
+            total,
+            percentage(audited, total)
+        );
+    }
+    if report.unsupported > 0 {
+        println!(
+            "{} users and groups of unsupported types are not counted",
+            report.unsupported
+        );
+    }
+
+    if report.uncovered.is_empty() {
+        println!("Every user and group is audited");
+    } else {
+        let mut table = Table::new();
+        table
+            .load_preset(UTF8_FULL)
+            .apply_modifier(UTF8_ROUND_CORNERS)
+            .apply_modifier(UTF8_SOLID_INNER_BORDERS)
+            .set_header(vec!["Name", "UPN", "Kind", "Type", "Location"]);
+        for item in report.uncovered.iter() {
+            table.add_row(vec![
+                item.display_name.clone(),
+                item.name.clone(),
+                item.kind.to_string(),
+                item.type_field.clone(),
+                item.location_type.to_string(),
+            ]);
+        }
+        println!("Not audited:");
+        println!("{table}");
+    }
+}
@@ -1,9 +1,17 @@
-use regex::Regex;
+use std::time::Duration;
+
+use dialoguer::Input;
 use reqwest::Client;
+use url::Url;
 use vauth::{Profile, build_url};
 
-use crate::{models::{config::Config, oauth::{CompleteResponse, AuthRequest, AuthResponse, CompleteRequest}}, tcplistener::run_tcp_listener};
+use crate::{models::{config::Config, oauth::{CompleteResponse, AuthRequest, AuthResponse, OAuthCallback}}, tcplistener::CallbackListener};
+
+const DEFAULT_CALLBACK_TIMEOUT: u64 = 300;
 
+/// Ctrl-C is handled by the callback listener from then on, so the calls that follow it are
+/// bounded by this instead.
+pub const SIGN_IN_REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
 
 
 pub async fn set_up_auth(
@@ -11,14 +19,32 @@ pub async fn set_up_auth(
     address: &String,
     profile: &Profile,
     client: &Client,
+    headless: bool,
 ) -> Result<CompleteResponse, anyhow::Error> {
+    // Bound before the sign-in is prepared, a port of 0 is only known once bound.
+    let listener = if headless {
+        // Nothing listens in headless mode, so there is no port to fill in.
+        if Url::parse(&config.azure.redirect_url).ok().and_then(|url| url.port()) == Some(0) {
+            return Err(anyhow::anyhow!(
+                "--headless needs a redirect_url with a fixed port, {} uses port 0",
+                config.azure.redirect_url
+            ));
+        }
+        None
+    } else {
+        Some(CallbackListener::bind(&config.azure.redirect_url, config.azure.bind_address.as_deref()).await?)
+    };
+    let redirect_url = match &listener {
+        Some(listener) => listener.redirect_url().to_string(),
+        None => config.azure.redirect_url.clone(),
+    };
 
     let auth_request = AuthRequest {
         authentication_service_kind: "Microsoft365".to_string(),
         tenant_id: config.azure.tenant_id.clone(),
         client_id: config.azure.client_id.clone(),
         client_secret: config.azure.client_secret.clone(),
-        redirect_url: config.azure.redirect_url.clone(),
+        redirect_url,
     };
     let url = build_url(
         address,
@@ -33,32 +59,46 @@ pub async fn set_up_auth(
         .json::<AuthResponse>()
         .await?;
 
+    // Microsoft hands the state back on the redirect, anything else did not come from this sign-in.
+    let expected_state = Url::parse(&response.sign_in_url)
+        .ok()
+        .and_then(|url| url.query_pairs().find(|(key, _)| key == "state").map(|(_, value)| value.into_owned()));
+    let timeout = Duration::from_secs(config.azure.callback_timeout.unwrap_or(DEFAULT_CALLBACK_TIMEOUT));
+
+    let listener = match listener {
+        Some(listener) => listener,
+        None => {
+            let callback = headless_callback(&response.sign_in_url)?;
+            return complete_sign_in(callback, expected_state.as_deref(), address, profile, client).await;
+        }
+    };
+
     println!("Opening browser to sign in...");
     webbrowser::open(&response.sign_in_url)?;
 
-    // let mut url_file = fs::File::create(&"callback.txt".to_string())?;
-    // url_file.write_all(b"replace this text")?;
-
     println!("Please sign in, this program will listen for the data from the call back.");
-    // press_btn_continue::wait("Press any key to continue...\n").unwrap();
-    // let url_string = fs::read_to_string("callback.txt")?;
-    let url_string = run_tcp_listener(config.azure.redirect_url.clone()).await?;
-    if url_string.is_empty() {
-        return Err(anyhow::anyhow!("The URL was empty"));
+    let (callback, pending) = listener.wait(expected_state.clone(), timeout).await?;
+    // The browser waits for the result so the page shows whether the consent really worked.
+    let result = complete_sign_in(callback, expected_state.as_deref(), address, profile, client).await;
+    match &result {
+        Ok(_) => pending.success().await,
+        Err(e) => pending.failure(&e.to_string()).await,
     }
-    let pattern = r"=([^&]+)";
-    let regex = Regex::new(pattern).unwrap();
-    let matches: Vec<&str> = regex
-        .captures_iter(&url_string)
-        .map(|capture| capture.get(1).unwrap().as_str())
-        .collect();
-    if matches.len() != 3 {
-        return Err(anyhow::anyhow!("Invalid URL"));
+    result
+}
+
+/// Exchanges the code from the redirect with VB365.
+async fn complete_sign_in(
+    callback: OAuthCallback,
+    expected_state: Option<&str>,
+    address: &String,
+    profile: &Profile,
+    client: &Client,
+) -> Result<CompleteResponse, anyhow::Error> {
+    if expected_state.is_some() && callback.state.as_deref() != expected_state {
+        return Err(anyhow::anyhow!("The state does not match this sign-in, start the setup again"));
     }
-    let complete_request = CompleteRequest {
-        code: matches[0].to_string(),
-        state: matches[1].to_string(),
-    };
+    let complete_request = callback.into_complete_request()?;
     let url_string = build_url(
         address,
         &"AuditEmailSettings/CompleteOAuthSignIn".to_string(),
@@ -67,6 +107,7 @@ pub async fn set_up_auth(
     let complete_response = client
         .post(&url_string)
         .json(&complete_request)
+        .timeout(SIGN_IN_REQUEST_TIMEOUT)
         .send()
         .await?;
 
@@ -77,6 +118,24 @@ pub async fn set_up_auth(
         let reason = complete_response.text().await?;
         Err(anyhow::anyhow!("Authentication failed! {:?}", reason))
     }
+}
 
-    // Ok(complete_response)
+/// Prints the sign-in URL and reads the redirect back from stdin, for when the browser is on another machine.
+fn headless_callback(sign_in_url: &str) -> Result<OAuthCallback, anyhow::Error> {
+    println!("Open this URL in a browser and sign in:\n\n{}\n", sign_in_url);
+    println!("The browser will then fail to load the redirect page, copy the address it was sent to.");
+    let pasted: String = Input::new()
+        .with_prompt("Redirect URL or code")
+        .interact_text()?;
+    let pasted = pasted.trim();
+
+    if pasted.contains('=') {
+        return Ok(OAuthCallback::parse(pasted));
+    }
+    let state: String = Input::new().with_prompt("State").interact_text()?;
+    Ok(OAuthCallback {
+        code: Some(pasted.to_string()),
+        state: Some(state.trim().to_string()),
+        ..Default::default()
+    })
 }
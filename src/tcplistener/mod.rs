@@ -1,43 +1,363 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    time::Duration,
+};
+
 use anyhow::Result;
-use regex::Regex;
-use std::net::TcpListener;
-use std::io::{Read, Write};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+    time,
+};
+use url::Url;
 
+use crate::models::oauth::OAuthCallback;
 
-pub async fn run_tcp_listener(address: String) -> Result<String> {
-    let re = Regex::new(r":(\d+)/").unwrap();
-    let port = if let Some(caps) = re.captures(&address) {
-        format!(":{}", caps[1].to_string())
-    } else {
-        ":80".to_string()
-    };
-    let address = format!("127.0.0.1{}", port);
-    let listener = TcpListener::bind(address)?;
+/// A callback is a single GET without a body, anything bigger is not one.
+const MAX_REQUEST_BYTES: usize = 16 * 1024;
+
+/// Browsers open spare connections that never send a request, these are dropped after this.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Page shown in the browser, filled in with the title, the heading colour and the message.
+const PAGE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>The Auditor - {title}</title>
+<style>
+body { font-family: Segoe UI, Helvetica, Arial, sans-serif; background: #f4f4f4; margin: 0; }
+main { max-width: 36rem; margin: 4rem auto; padding: 2rem; background: #fff; border-radius: 8px; }
+h1 { color: {colour}; margin-top: 0; }
+p { white-space: pre-wrap; word-break: break-word; }
+</style>
+</head>
+<body>
+<main>
+<h1>{title}</h1>
+<p>{message}</p>
+<small>The Auditor for Veeam Backup for Microsoft 365</small>
+</main>
+</body>
+</html>
+"#;
 
-    println!("Listening on {}", listener.local_addr()?);
+/// The browser's connection for the callback, held open until the sign-in has been completed.
+pub struct PendingResponse {
+    stream: TcpStream,
+}
 
-    let mut data = String::new();
+impl PendingResponse {
+    pub async fn success(mut self) {
+        let body = page(true, "Sign-in complete", "The Auditor is signed in to Microsoft 365, you can close this window now.");
+        let _ = respond(&mut self.stream, "200 OK", &body).await;
+    }
+
+    pub async fn failure(mut self, message: &str) {
+        let body = page(false, "Sign-in failed", message);
+        let _ = respond(&mut self.stream, "200 OK", &body).await;
+    }
+}
 
-    for stream in listener.incoming() {
-        match stream {
-            Ok(mut stream) => {
-                let mut buffer = [0; 2048];
-                stream.read(&mut buffer)?;
-                let request = String::from_utf8_lossy(&buffer[..]);
+/// Listener for the OAuth redirect, bound before the sign-in is prepared so the port is known.
+pub struct CallbackListener {
+    listener: TcpListener,
+    redirect_url: String,
+    path: String,
+}
 
-                if request.starts_with("GET") {
-                    let re = Regex::new(r"GET /([^ ]+)").unwrap();
-                    data = re.captures(&request).unwrap()[1].to_string();
-                    let http_response = "HTTP/1.1 200 OK\r\n\r\n You can close this window now.".to_string();
-                    stream.write_all(http_response.as_bytes()).expect("Failed to write to stream");
+impl CallbackListener {
+    /// Binds the port from `redirect_url`, on `bind_address` when it is set and 127.0.0.1 otherwise.
+    ///
+    /// `bind_address` is an IP address, with a port when it differs from the one in `redirect_url`,
+    /// such as behind a proxy. A port of 0 in `redirect_url` lets the system pick a free one.
+    pub async fn bind(redirect_url: &str, bind_address: Option<&str>) -> Result<CallbackListener> {
+        let mut url = Url::parse(redirect_url)
+            .map_err(|e| anyhow::anyhow!("The redirect_url {} is not a valid URL: {}", redirect_url, e))?;
+        let port = url
+            .port_or_known_default()
+            .ok_or_else(|| anyhow::anyhow!("The redirect_url {} has no port", redirect_url))?;
 
-                    break;
+        let address = match bind_address {
+            None => SocketAddr::from((Ipv4Addr::LOCALHOST, port)),
+            Some(bind) => match (bind.parse::<SocketAddr>(), bind.parse::<IpAddr>()) {
+                (Ok(address), _) => address,
+                (_, Ok(ip)) => SocketAddr::new(ip, port),
+                _ => {
+                    return Err(anyhow::anyhow!(
+                        "The bind_address {} must be an IP address, optionally with a port",
+                        bind
+                    ))
+                }
+            },
+        };
+        let listener = TcpListener::bind(address)
+            .await
+            .map_err(|e| anyhow::anyhow!("Unable to listen on {}: {}", address, e))?;
+
+        let redirect_url = if url.port() == Some(0) {
+            let _ = url.set_port(Some(listener.local_addr()?.port()));
+            url.to_string()
+        } else {
+            redirect_url.to_string()
+        };
+        Ok(CallbackListener {
+            listener,
+            redirect_url,
+            path: normalize_path(url.path()).to_string(),
+        })
+    }
+
+    /// The redirect url to sign in with, with the port filled in when the system picked it.
+    pub fn redirect_url(&self) -> &str {
+        &self.redirect_url
+    }
+
+    /// Waits for the OAuth redirect and returns its parameters with the connection to answer it on.
+    ///
+    /// Requests to other paths or without a code or error, such as the browser asking for
+    /// /favicon.ico, get a 404 and the listener keeps waiting. Callbacks, including errors, with
+    /// a different `state` are rejected. Gives up after `timeout` or when Ctrl-C is pressed.
+    pub async fn wait(
+        self,
+        expected_state: Option<String>,
+        timeout: Duration,
+    ) -> Result<(OAuthCallback, PendingResponse)> {
+        let CallbackListener { listener, path, .. } = self;
+        println!("Listening on {}", listener.local_addr()?);
+
+        let (sender, mut receiver) = mpsc::channel::<(OAuthCallback, PendingResponse)>(1);
+        let accept = tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => {
+                        let sender = sender.clone();
+                        let expected_state = expected_state.clone();
+                        let path = path.clone();
+                        tokio::spawn(async move {
+                            if let Some(received) = handle_connection(stream, &path, expected_state.as_deref()).await {
+                                let _ = sender.send(received).await;
+                            }
+                        });
+                    }
+                    Err(e) => println!("Unable to connect: {}", e),
                 }
             }
-            Err(e) => {
-                println!("Unable to connect: {}", e);
-            }
+        });
+
+        let result = tokio::select! {
+            received = receiver.recv() => received.ok_or_else(|| anyhow::anyhow!("The listener stopped")),
+            _ = time::sleep(timeout) => Err(anyhow::anyhow!(
+                "Timed out after {} seconds waiting for the sign-in callback",
+                timeout.as_secs()
+            )),
+            _ = tokio::signal::ctrl_c() => Err(anyhow::anyhow!("Sign-in cancelled")),
+        };
+        accept.abort();
+        result
+    }
+}
+
+/// Answers anything that is not the callback, returns the callback or `None` to keep waiting.
+async fn handle_connection(
+    mut stream: TcpStream,
+    callback_path: &str,
+    expected_state: Option<&str>,
+) -> Option<(OAuthCallback, PendingResponse)> {
+    let request = match time::timeout(READ_TIMEOUT, read_request(&mut stream)).await {
+        Ok(Ok(request)) => request,
+        _ => return None,
+    };
+    let (path, query) = match parse_request_line(&request) {
+        Some(target) => target,
+        None => {
+            let _ = respond(&mut stream, "400 Bad Request", &page(false, "Bad request", "This is not a sign-in callback.")).await;
+            return None;
+        }
+    };
+
+    let callback = OAuthCallback::parse(query);
+    if normalize_path(path) != callback_path || !callback.is_callback() {
+        let _ = respond(&mut stream, "404 Not Found", &page(false, "Not found", "This is not a sign-in callback.")).await;
+        return None;
+    }
+    // Error redirects carry the state too, otherwise any page could abort the sign-in.
+    if expected_state.is_some() && callback.state.as_deref() != expected_state {
+        println!("Ignored a callback to {} with an unexpected state", path);
+        let body = page(false, "Sign-in failed", "The sign-in state does not match, start the setup again.");
+        let _ = respond(&mut stream, "400 Bad Request", &body).await;
+        return None;
+    }
+
+    Some((callback, PendingResponse { stream }))
+}
+
+/// Treats "/callback" and "/callback/" as the same path.
+fn normalize_path(path: &str) -> &str {
+    match path.trim_end_matches('/') {
+        "" => "/",
+        path => path,
+    }
+}
+
+/// Reads up to the end of the request headers.
+async fn read_request(stream: &mut TcpStream) -> Result<String> {
+    let mut request: Vec<u8> = Vec::new();
+    let mut buffer = [0; 2048];
+    loop {
+        let read = stream.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        request.extend_from_slice(&buffer[..read]);
+        if request.windows(4).any(|x| x == b"\r\n\r\n") {
+            break;
+        }
+        if request.len() > MAX_REQUEST_BYTES {
+            return Err(anyhow::anyhow!("Request too large"));
         }
     }
-    Ok(data)
-}
\ No newline at end of file
+    Ok(String::from_utf8_lossy(&request).into_owned())
+}
+
+/// Splits a request line such as "GET /callback?code=1 HTTP/1.1" into the path and query.
+fn parse_request_line(request: &str) -> Option<(&str, &str)> {
+    let mut parts = request.lines().next()?.split_whitespace();
+    let (method, target, version) = (parts.next()?, parts.next()?, parts.next()?);
+    if method != "GET" || !version.starts_with("HTTP/") || parts.next().is_some() {
+        return None;
+    }
+    Some(target.split_once('?').unwrap_or((target, "")))
+}
+
+fn page(success: bool, title: &str, message: &str) -> String {
+    let colour = if success { "#00b336" } else { "#c8102e" };
+    PAGE.replace("{colour}", colour)
+        .replace("{title}", &escape_html(title))
+        .replace("{message}", &escape_html(message))
+}
+
+/// Error text from Microsoft or VB365 ends up in the page, so it is escaped.
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+async fn respond(stream: &mut TcpStream, status: &str, body: &str) -> Result<()> {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A port that was free a moment ago.
+    async fn free_port() -> u16 {
+        TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap().port()
+    }
+
+    #[tokio::test]
+    async fn binds_the_port_of_a_url_without_a_trailing_slash() {
+        let port = free_port().await;
+        let redirect_url = format!("http://localhost:{}", port);
+        let listener = CallbackListener::bind(&redirect_url, None).await.unwrap();
+        assert_eq!(listener.listener.local_addr().unwrap(), SocketAddr::from((Ipv4Addr::LOCALHOST, port)));
+        assert_eq!(listener.redirect_url(), redirect_url);
+        assert_eq!(listener.path, "/");
+    }
+
+    #[tokio::test]
+    async fn port_zero_is_filled_in() {
+        let listener = CallbackListener::bind("http://localhost:0/cb", None).await.unwrap();
+        let port = listener.listener.local_addr().unwrap().port();
+        assert_ne!(port, 0);
+        assert_eq!(listener.redirect_url(), format!("http://localhost:{}/cb", port));
+        assert_eq!(listener.path, "/cb");
+    }
+
+    #[tokio::test]
+    async fn bind_address_without_a_port_uses_the_redirect_port() {
+        let port = free_port().await;
+        let redirect_url = format!("http://auditor.contoso.com:{}/cb", port);
+        let listener = CallbackListener::bind(&redirect_url, Some("127.0.0.1")).await.unwrap();
+        assert_eq!(listener.listener.local_addr().unwrap().port(), port);
+        assert_eq!(listener.redirect_url(), redirect_url);
+    }
+
+    #[tokio::test]
+    async fn bind_address_with_a_port_overrides_the_redirect_port() {
+        let port = free_port().await;
+        let bind_address = format!("127.0.0.1:{}", port);
+        let listener = CallbackListener::bind("https://auditor.contoso.com/cb", Some(&bind_address)).await.unwrap();
+        assert_eq!(listener.listener.local_addr().unwrap().port(), port);
+        assert_eq!(listener.redirect_url(), "https://auditor.contoso.com/cb");
+    }
+
+    #[tokio::test]
+    async fn invalid_bind_address_is_rejected() {
+        let error = CallbackListener::bind("http://localhost:0/", Some("localhost")).await.err().unwrap();
+        assert!(error.to_string().contains("must be an IP address"));
+    }
+
+    #[tokio::test]
+    async fn callbacks_need_the_expected_state() {
+        let listener = CallbackListener::bind("http://localhost:0/cb", None).await.unwrap();
+        let redirect_url = listener.redirect_url().to_string();
+        let wait = tokio::spawn(listener.wait(Some("abc".to_string()), Duration::from_secs(10)));
+
+        let client = reqwest::Client::new();
+        for query in ["error=access_denied", "error=access_denied&state=other", "code=1&state=other"] {
+            let response = client.get(format!("{}?{}", redirect_url, query)).send().await.unwrap();
+            assert_eq!(response.status(), 400, "{}", query);
+        }
+        let response = client.get(format!("{}/favicon.ico", redirect_url)).send().await.unwrap();
+        assert_eq!(response.status(), 404);
+        assert!(!wait.is_finished());
+
+        let browser = tokio::spawn(async move {
+            client.get(format!("{}?error=access_denied&state=abc", redirect_url)).send().await.unwrap()
+        });
+        let (callback, pending) = wait.await.unwrap().unwrap();
+        assert_eq!(callback.error.as_deref(), Some("access_denied"));
+        pending.failure("access_denied").await;
+        assert_eq!(browser.await.unwrap().status(), 200);
+    }
+
+    #[test]
+    fn trailing_slashes_match_the_same_path() {
+        assert_eq!(normalize_path("/cb"), normalize_path("/cb/"));
+        assert_eq!(normalize_path(""), "/");
+        assert_eq!(normalize_path("/"), "/");
+        assert_ne!(normalize_path("/cb"), normalize_path("/other"));
+    }
+
+    #[test]
+    fn parses_get_request_lines() {
+        assert_eq!(
+            parse_request_line("GET /cb?code=1&state=2 HTTP/1.1\r\nHost: localhost\r\n\r\n"),
+            Some(("/cb", "code=1&state=2"))
+        );
+        assert_eq!(parse_request_line("GET /cb HTTP/1.1\r\n\r\n"), Some(("/cb", "")));
+    }
+
+    #[test]
+    fn rejects_other_request_lines() {
+        assert_eq!(parse_request_line("POST /cb?code=1 HTTP/1.1\r\n\r\n"), None);
+        assert_eq!(parse_request_line("GET /cb?code=1\r\n\r\n"), None);
+        assert_eq!(parse_request_line("GET /cb?code=1 SMTP/1.0\r\n\r\n"), None);
+        assert_eq!(parse_request_line("GET /cb ?code=1 HTTP/1.1\r\n\r\n"), None);
+        assert_eq!(parse_request_line(""), None);
+    }
+}
@@ -0,0 +1,130 @@
+use std::{fs::File, io::BufReader, sync::Arc, time::SystemTime};
+
+use anyhow::Result;
+use reqwest::ClientBuilder;
+use rustls::{
+    client::{ServerCertVerified, ServerCertVerifier},
+    Certificate, ClientConfig, ServerName,
+};
+use sha2::{Digest, Sha256};
+
+use crate::models::config::Vb356;
+
+/// Returns a client builder with certificate verification set up from the vb365 config.
+///
+/// A pinned fingerprint takes precedence over the CA bundle, the pinned certificate is trusted
+/// even when it is self-signed.
+pub fn client_builder(vb365: &Vb356) -> Result<ClientBuilder> {
+    if vb365.pin_sha256.is_some() && vb365.ca_bundle.is_some() {
+        return Err(anyhow::anyhow!(
+            "Set either pin_sha256 or ca_bundle in [vb365], a pinned certificate does not use the CA bundle"
+        ));
+    }
+    let mut builder = reqwest::Client::builder();
+    if vb365.insecure {
+        return Ok(builder.danger_accept_invalid_certs(true));
+    }
+    if let Some(pin) = &vb365.pin_sha256 {
+        let verifier = PinnedVerifier {
+            fingerprint: parse_fingerprint(pin)?,
+        };
+        let config = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_custom_certificate_verifier(Arc::new(verifier))
+            .with_no_client_auth();
+        return Ok(builder.use_preconfigured_tls(config));
+    }
+    if let Some(path) = &vb365.ca_bundle {
+        let mut reader = BufReader::new(File::open(path)?);
+        let certs = rustls_pemfile::certs(&mut reader)?;
+        if certs.is_empty() {
+            return Err(anyhow::anyhow!("No certificates found in {}", path));
+        }
+        for der in certs {
+            builder = builder.add_root_certificate(reqwest::Certificate::from_der(&der)?);
+        }
+    }
+    Ok(builder)
+}
+
+/// Accepts "AB:CD:..." as shown by browsers as well as plain hex.
+fn parse_fingerprint(pin: &str) -> Result<String> {
+    let fingerprint: String = pin
+        .chars()
+        .filter(|x| *x != ':' && !x.is_whitespace())
+        .collect::<String>()
+        .to_lowercase();
+    if fingerprint.len() != 64 || !fingerprint.chars().all(|x| x.is_ascii_hexdigit()) {
+        return Err(anyhow::anyhow!("pin_sha256 must be a SHA-256 fingerprint in hex"));
+    }
+    Ok(fingerprint)
+}
+
+fn fingerprint(der: &[u8]) -> String {
+    Sha256::digest(der)
+        .iter()
+        .map(|x| format!("{:02x}", x))
+        .collect()
+}
+
+struct PinnedVerifier {
+    fingerprint: String,
+}
+
+impl ServerCertVerifier for PinnedVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let presented = fingerprint(&end_entity.0);
+        if presented == self.fingerprint {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::General(format!(
+                "certificate fingerprint {} does not match pin_sha256",
+                presented
+            )))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEX: &str = "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+
+    #[test]
+    fn accepts_plain_hex() {
+        assert_eq!(parse_fingerprint(HEX).unwrap(), HEX);
+    }
+
+    #[test]
+    fn accepts_colons_whitespace_and_uppercase() {
+        let browser_style = HEX
+            .to_uppercase()
+            .as_bytes()
+            .chunks(2)
+            .map(|x| std::str::from_utf8(x).unwrap())
+            .collect::<Vec<_>>()
+            .join(":");
+        assert_eq!(parse_fingerprint(&format!("  {}\n", browser_style)).unwrap(), HEX);
+    }
+
+    #[test]
+    fn rejects_the_wrong_length() {
+        assert!(parse_fingerprint(&HEX[..62]).is_err());
+        assert!(parse_fingerprint(&format!("{}00", HEX)).is_err());
+        assert!(parse_fingerprint("").is_err());
+    }
+
+    #[test]
+    fn rejects_non_hex() {
+        assert!(parse_fingerprint(&format!("{}zz", &HEX[..62])).is_err());
+    }
+}
@@ -0,0 +1,303 @@
+use std::{
+    cell::OnceCell,
+    env, fs,
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+use aes_gcm::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng},
+    Aes256Gcm, Key, Nonce,
+};
+use anyhow::Result;
+use argon2::Argon2;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use dialoguer::Password;
+
+use crate::models::config::{EncryptedToken, LoginExtended, TokenStoreKind, Vb356};
+
+const KEYRING_SERVICE: &str = "the_auditor";
+
+/// Where the VB365 access and refresh tokens are kept between runs.
+pub trait TokenStore {
+    fn load(&self) -> Result<Option<LoginExtended>>;
+    fn save(&mut self, token: &LoginExtended) -> Result<()>;
+}
+
+pub fn token_store(vb365: &Vb356) -> Result<Box<dyn TokenStore>> {
+    let store: Box<dyn TokenStore> = match vb365.token_store {
+        TokenStoreKind::File => Box::new(FileStore {
+            path: cache_path(vb365, "tokens", "json")?,
+        }),
+        TokenStoreKind::Encrypted => Box::new(EncryptedFileStore {
+            path: cache_path(vb365, "tokens", "enc")?,
+            passphrase: OnceCell::new(),
+        }),
+        TokenStoreKind::Keyring => Box::new(KeyringStore {
+            entry: keyring::Entry::new(
+                KEYRING_SERVICE,
+                &format!("{}@{}", vb365.username, vb365.address),
+            )?,
+        }),
+        TokenStoreKind::Memory => Box::new(MemoryStore { token: None }),
+    };
+    Ok(store)
+}
+
+/// File for the server and user in a cache sub directory, e.g.
+/// ~/.cache/the_auditor/tokens/10_0_0_1_admin.json on Linux.
+pub fn cache_path(vb365: &Vb356, sub_dir: &str, extension: &str) -> Result<PathBuf> {
+    let dir = dirs::cache_dir()
+        .ok_or_else(|| anyhow::anyhow!("Unable to find the user cache directory"))?
+        .join("the_auditor")
+        .join(sub_dir);
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    builder.create(&dir)?;
+    let key: String = format!("{}_{}", vb365.address, vb365.username)
+        .chars()
+        .map(|x| if x.is_ascii_alphanumeric() || x == '-' { x } else { '_' })
+        .collect();
+    Ok(dir.join(format!("{}.{}", key, extension)))
+}
+
+/// Writes the file so that only the current user can read it.
+pub fn write_private(path: &Path, contents: &[u8]) -> Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    #[cfg(unix)]
+    {
+        // The mode only applies to new files, tighten files left by older versions.
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    }
+    file.write_all(contents)?;
+    Ok(())
+}
+
+pub fn read_optional(path: &Path) -> Result<Option<String>> {
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Plaintext JSON file.
+pub struct FileStore {
+    path: PathBuf,
+}
+
+impl TokenStore for FileStore {
+    fn load(&self) -> Result<Option<LoginExtended>> {
+        match read_optional(&self.path)? {
+            Some(contents) => Ok(Some(serde_json::from_str(&contents)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn save(&mut self, token: &LoginExtended) -> Result<()> {
+        // Warn once, when the plaintext file is first created.
+        if !self.path.exists() {
+            eprintln!(
+                "Warning: the VB365 tokens are saved unencrypted in {}, set token_store to encrypted or keyring in [vb365] to protect them.",
+                self.path.display()
+            );
+        }
+        let token_string = serde_json::to_string_pretty(token)?;
+        write_private(&self.path, token_string.as_bytes())
+    }
+}
+
+/// AES-256-GCM encrypted file with the key derived from a passphrase using Argon2.
+///
+/// The passphrase is read from AUDITOR_TOKEN_PASSPHRASE or prompted for.
+pub struct EncryptedFileStore {
+    path: PathBuf,
+    passphrase: OnceCell<String>,
+}
+
+impl EncryptedFileStore {
+    fn passphrase(&self) -> Result<&String> {
+        if let Some(passphrase) = self.passphrase.get() {
+            return Ok(passphrase);
+        }
+        let passphrase = match env::var("AUDITOR_TOKEN_PASSPHRASE") {
+            Ok(passphrase) => passphrase,
+            Err(_) => Password::new()
+                .with_prompt("Token store passphrase")
+                .interact()?,
+        };
+        if passphrase.is_empty() {
+            return Err(anyhow::anyhow!("The token store passphrase cannot be empty"));
+        }
+        Ok(self.passphrase.get_or_init(|| passphrase))
+    }
+
+    fn cipher(&self, salt: &[u8]) -> Result<Aes256Gcm> {
+        let mut key = [0u8; 32];
+        Argon2::default()
+            .hash_password_into(self.passphrase()?.as_bytes(), salt, &mut key)
+            .map_err(|e| anyhow::anyhow!("Unable to derive the token key: {}", e))?;
+        Ok(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)))
+    }
+}
+
+impl TokenStore for EncryptedFileStore {
+    fn load(&self) -> Result<Option<LoginExtended>> {
+        let contents = match read_optional(&self.path)? {
+            Some(contents) => contents,
+            None => return Ok(None),
+        };
+        let encrypted: EncryptedToken = serde_json::from_str(&contents)?;
+        if encrypted.version != 1 {
+            return Err(anyhow::anyhow!("Unsupported token file version {}", encrypted.version));
+        }
+        let salt = STANDARD.decode(encrypted.salt)?;
+        let nonce = STANDARD.decode(encrypted.nonce)?;
+        let ciphertext = STANDARD.decode(encrypted.ciphertext)?;
+        let plaintext = self
+            .cipher(&salt)?
+            .decrypt(Nonce::from_slice(&nonce), ciphertext.as_ref())
+            .map_err(|_| anyhow::anyhow!("Unable to decrypt the token file, wrong passphrase?"))?;
+        Ok(Some(serde_json::from_slice(&plaintext)?))
+    }
+
+    fn save(&mut self, token: &LoginExtended) -> Result<()> {
+        let mut salt = [0u8; 16];
+        OsRng.fill_bytes(&mut salt);
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let plaintext = serde_json::to_vec(token)?;
+        let ciphertext = self
+            .cipher(&salt)?
+            .encrypt(&nonce, plaintext.as_ref())
+            .map_err(|_| anyhow::anyhow!("Unable to encrypt the token"))?;
+        let encrypted = EncryptedToken {
+            version: 1,
+            salt: STANDARD.encode(salt),
+            nonce: STANDARD.encode(nonce),
+            ciphertext: STANDARD.encode(ciphertext),
+        };
+        write_private(&self.path, serde_json::to_string_pretty(&encrypted)?.as_bytes())
+    }
+}
+
+/// OS keyring, the Secret Service on Linux, the Keychain on macOS and the Credential Manager on Windows.
+pub struct KeyringStore {
+    entry: keyring::Entry,
+}
+
+/// The Secret Service client blocks on its own runtime, which panics on a tokio worker thread.
+fn off_runtime<T: Send>(f: impl FnOnce() -> T + Send) -> T {
+    std::thread::scope(|scope| scope.spawn(f).join().expect("keyring thread panicked"))
+}
+
+impl TokenStore for KeyringStore {
+    fn load(&self) -> Result<Option<LoginExtended>> {
+        match off_runtime(|| self.entry.get_password()) {
+            Ok(secret) => Ok(Some(serde_json::from_str(&secret)?)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn save(&mut self, token: &LoginExtended) -> Result<()> {
+        let secret = serde_json::to_string(token)?;
+        off_runtime(|| self.entry.set_password(&secret))?;
+        Ok(())
+    }
+}
+
+/// Keeps the token for a single run only.
+pub struct MemoryStore {
+    token: Option<LoginExtended>,
+}
+
+impl TokenStore for MemoryStore {
+    fn load(&self) -> Result<Option<LoginExtended>> {
+        Ok(self.token.clone())
+    }
+
+    fn save(&mut self, token: &LoginExtended) -> Result<()> {
+        self.token = Some(token.clone());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh path in the temp directory for each test.
+    fn temp_path(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("the_auditor_{}_{}", std::process::id(), name));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn encrypted_store(path: &Path, passphrase: &str) -> EncryptedFileStore {
+        EncryptedFileStore {
+            path: path.to_path_buf(),
+            passphrase: OnceCell::from(passphrase.to_string()),
+        }
+    }
+
+    fn token() -> LoginExtended {
+        LoginExtended {
+            access_token: "access-secret".to_string(),
+            refresh_token: "refresh-secret".to_string(),
+            address: "vb365.contoso.com".to_string(),
+            username: "admin".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn encrypted_store_round_trips() {
+        let path = temp_path("round_trip.enc");
+        encrypted_store(&path, "correct horse").save(&token()).unwrap();
+
+        let contents = fs::read_to_string(&path).unwrap();
+        assert!(!contents.contains("access-secret"));
+        assert!(!contents.contains("refresh-secret"));
+        assert_eq!(encrypted_store(&path, "correct horse").load().unwrap(), Some(token()));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn encrypted_store_rejects_a_wrong_passphrase() {
+        let path = temp_path("wrong_passphrase.enc");
+        encrypted_store(&path, "correct horse").save(&token()).unwrap();
+
+        let error = encrypted_store(&path, "battery staple").load().unwrap_err();
+        assert!(error.to_string().contains("wrong passphrase"));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_private_is_only_readable_by_the_user() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = temp_path("private.json");
+        write_private(&path, b"{}").unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+
+        // Files left by older versions are tightened too.
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        write_private(&path, b"{}").unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,268 @@
+use std::fs::{self, File};
+
+use anyhow::Result;
+use chrono::Utc;
+use reqwest::Client;
+use vauth::{Profile, VProfile};
+
+use crate::{
+    audit::{audit_items, confirm_action, org_groups, org_sites, org_teams, org_users, post_audit_items},
+    cli::{DirectoryFilter, ExportArgs, ImportArgs},
+    login::{get_org, login, select_orgs},
+    models::{
+        self,
+        audit::AuditItem,
+        config::Vb356,
+        export::{AuditExport, AuditExportV1, ExportVersion, OrgExport, EXPORT_VERSION},
+        kind::ItemKind,
+    },
+    plan::resolve,
+};
+
+pub async fn export_audit_items(vb365: &Vb356, args: &ExportArgs) -> Result<()> {
+    let address = &vb365.address;
+    let mut profile = Profile::get_profile(VProfile::VB365);
+
+    let client = login(vb365, &mut profile).await?;
+    let orgs = select_orgs(address, &profile, &client, args.org.as_deref(), args.all_orgs).await?;
+
+    let mut export = AuditExport {
+        version: EXPORT_VERSION,
+        exported_on: Utc::now().to_rfc3339(),
+        server: address.clone(),
+        orgs: Vec::new(),
+    };
+    for org in orgs {
+        let items = audit_items(&org.id, address, &profile, &client).await?;
+        println!("{} audit items exported from {}", items.len(), org.name);
+        export.orgs.push(OrgExport {
+            org: org.name,
+            org_id: org.id,
+            items,
+        });
+    }
+    serde_json::to_writer_pretty(File::create(&args.output)?, &export)?;
+    println!("Export saved to {}", args.output);
+    Ok(())
+}
+
+fn read_export(path: &str) -> Result<AuditExport> {
+    parse_export(&fs::read_to_string(path)?)
+}
+
+/// Version 1 files from before `--all-orgs` are read as a single organization.
+fn parse_export(file_string: &str) -> Result<AuditExport> {
+    let version: ExportVersion = serde_json::from_str(file_string)?;
+    match version.version {
+        1 => Ok(serde_json::from_str::<AuditExportV1>(file_string)?.into()),
+        EXPORT_VERSION => Ok(serde_json::from_str(file_string)?),
+        version => Err(anyhow::anyhow!("Unsupported export version {}", version)),
+    }
+}
+
+/// Directory of the target organization, only the kinds found in the export are fetched.
+#[derive(Default)]
+struct Directory {
+    users: Vec<models::user::Result>,
+    groups: Vec<models::group::Result>,
+    sites: Vec<models::site::Result>,
+    teams: Vec<models::team::Result>,
+}
+
+/// Matches the exported directory id, then the name (UPN, site url or team mail), then the
+/// display name, so items are found again when the ids differ on the new server.
+fn find<'a, T>(
+    item: &AuditItem,
+    items: &'a [T],
+    fields: impl Fn(&T) -> (&str, &str, &str),
+) -> Result<&'a T, String> {
+    let (display_name, id) = item.display_name_and_id();
+    let name = item.name();
+    let mut reason = format!("{} has no id or name", display_name);
+    for entry in [id, name, display_name] {
+        if entry.is_empty() {
+            continue;
+        }
+        match resolve(&entry, items, &fields) {
+            Ok(found) => return Ok(found),
+            Err(e) => reason = e,
+        }
+    }
+    Err(reason)
+}
+
+fn resolve_item(item: &AuditItem, directory: &Directory) -> Result<AuditItem, String> {
+    match item.type_field {
+        ItemKind::User => {
+            let user = find(item, &directory.users, |x| (&x.id, &x.name, &x.display_name))
+                .map_err(|e| format!("user {}", e))?;
+            if !user.type_field.is_supported() {
+                return Err(format!("user {} has unsupported type {}", user.display_name, user.type_field));
+            }
+            Ok(AuditItem::from(user.clone()))
+        }
+        ItemKind::Group => {
+            let group = find(item, &directory.groups, |x| (&x.id, &x.name, &x.display_name))
+                .map_err(|e| format!("group {}", e))?;
+            if !group.type_field.is_supported() {
+                return Err(format!("group {} has unsupported type {}", group.display_name, group.type_field));
+            }
+            Ok(AuditItem::from(group.clone()))
+        }
+        ItemKind::Site => find(item, &directory.sites, |x| (&x.id, &x.url, &x.title))
+            .map(|x| AuditItem::from(x.clone()))
+            .map_err(|e| format!("site {}", e)),
+        ItemKind::Team => find(item, &directory.teams, |x| (&x.id, &x.mail, &x.display_name))
+            .map(|x| AuditItem::from(x.clone()))
+            .map_err(|e| format!("team {}", e)),
+        ItemKind::Unknown(ref kind) => Err(format!(
+            "{} has unsupported audit item type {}",
+            item.display_name_and_id().0,
+            kind
+        )),
+    }
+}
+
+pub async fn import_audit_items(vb365: &Vb356, args: &ImportArgs) -> Result<()> {
+    let address = &vb365.address;
+    let export = read_export(&args.file)?;
+    if args.org.is_some() && export.orgs.len() > 1 {
+        return Err(anyhow::anyhow!(
+            "The export has {} organizations, --org can only be used with one",
+            export.orgs.len()
+        ));
+    }
+
+    let mut profile = Profile::get_profile(VProfile::VB365);
+    let client = login(vb365, &mut profile).await?;
+
+    let mut failed = 0;
+    for org_export in export.orgs.iter() {
+        println!(
+            "Importing {} audit items exported from {} on {}",
+            org_export.items.len(),
+            org_export.org,
+            export.server
+        );
+        let org = args.org.as_deref().unwrap_or(&org_export.org);
+        let org_id = get_org(address, &profile, &client, Some(org)).await?.id;
+        failed += import_org(org_export, &org_id, args.yes, address, &profile, &client).await?;
+    }
+
+    if failed > 0 {
+        return Err(anyhow::anyhow!("{} audit items could not be imported", failed));
+    }
+    Ok(())
+}
+
+/// Imports the items of one exported organization, returns the number that failed.
+async fn import_org(
+    org_export: &OrgExport,
+    org_id: &String,
+    yes: bool,
+    address: &String,
+    profile: &Profile,
+    client: &Client,
+) -> Result<usize> {
+    let has_kind = |kind: ItemKind| org_export.items.iter().any(|x| x.type_field == kind);
+    let filter = DirectoryFilter::default();
+    let mut directory = Directory::default();
+    if has_kind(ItemKind::User) {
+        directory.users = org_users(org_id, address, profile, client, &filter).await?.results;
+    }
+    if has_kind(ItemKind::Group) {
+        directory.groups = org_groups(org_id, address, profile, client, &filter).await?.results;
+    }
+    if has_kind(ItemKind::Site) {
+        directory.sites = org_sites(org_id, address, profile, client, &filter).await?.results;
+    }
+    if has_kind(ItemKind::Team) {
+        directory.teams = org_teams(org_id, address, profile, client, &filter).await?.results;
+    }
+
+    let audited_ids: Vec<String> = audit_items(org_id, address, profile, client)
+        .await?
+        .iter()
+        .map(|x| x.display_name_and_id().1)
+        .collect();
+
+    let mut already_audited = 0;
+    let mut unresolved: Vec<String> = Vec::new();
+    let mut new_items: Vec<AuditItem> = Vec::new();
+    for item in org_export.items.iter() {
+        match resolve_item(item, &directory) {
+            Ok(resolved) => {
+                let (name, id) = resolved.display_name_and_id();
+                if audited_ids.contains(&id) {
+                    println!("  = {} is already audited", name);
+                    already_audited += 1;
+                } else if !new_items.iter().any(|x| x.display_name_and_id().1 == id) {
+                    println!("  + {}", name);
+                    new_items.push(resolved);
+                }
+            }
+            Err(reason) => {
+                println!("  ! {}", reason);
+                unresolved.push(reason);
+            }
+        }
+    }
+
+    let mut rejected = 0;
+    if !new_items.is_empty() {
+        println!("This will add {} audit items", new_items.len());
+        confirm_action(yes);
+        let failed = post_audit_items(&new_items, org_id, address, profile, client).await?;
+        for (item, reason) in failed.iter() {
+            println!("  ! {} was rejected: {}", item.display_name_and_id().0, reason);
+        }
+        rejected = failed.len();
+    }
+    println!(
+        "{} already audited, {} newly added, {} rejected, {} unresolved.",
+        already_audited,
+        new_items.len() - rejected,
+        rejected,
+        unresolved.len()
+    );
+    Ok(rejected + unresolved.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ITEM: &str = r#"{"type": "User", "user": {"displayName": "Adele Vance", "id": "u1", "locationType": "Cloud", "name": "adele@contoso.com"}}"#;
+
+    #[test]
+    fn reads_single_org_version_1_files() {
+        let file = format!(
+            r#"{{"version": 1, "exportedOn": "2024-01-01T00:00:00Z", "server": "vb365", "org": "Contoso", "orgId": "o1", "items": [{}]}}"#,
+            ITEM
+        );
+        let export = parse_export(&file).unwrap();
+        assert_eq!(export.version, EXPORT_VERSION);
+        assert_eq!(export.server, "vb365");
+        assert_eq!(export.orgs.len(), 1);
+        assert_eq!(export.orgs[0].org, "Contoso");
+        assert_eq!(export.orgs[0].org_id, "o1");
+        assert_eq!(export.orgs[0].items[0].name(), "adele@contoso.com");
+    }
+
+    #[test]
+    fn reads_multi_org_version_2_files() {
+        let file = format!(
+            r#"{{"version": 2, "exportedOn": "", "server": "vb365", "orgs": [{{"org": "Contoso", "orgId": "o1", "items": [{}]}}, {{"org": "Fabrikam", "orgId": "o2", "items": []}}]}}"#,
+            ITEM
+        );
+        let export = parse_export(&file).unwrap();
+        assert_eq!(export.orgs.len(), 2);
+        assert_eq!(export.orgs[1].org, "Fabrikam");
+    }
+
+    #[test]
+    fn rejects_unknown_versions() {
+        let error = parse_export(r#"{"version": 3, "orgs": "anything"}"#).unwrap_err();
+        assert_eq!(error.to_string(), "Unsupported export version 3");
+    }
+}